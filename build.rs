@@ -1,25 +1,139 @@
-extern crate gcc;
+extern crate cc;
+extern crate pkg_config;
 
+use std::env;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 
-fn main() {
-    let mut status = Command::new("ld").arg("-lcpupower").status();
+/// Features choosing the backend, at most one of them may be enabled
+const BACKEND_FEATURES: [&str; 5] = ["cpupower", "cpufreq-legacy", "dynamic", "mock", "native-sysfs"];
+
+/// Directory to look for the library in, instead of pkg-config and the linker defaults
+const LIB_DIR_VARIABLE: &str = "CPUFREQ_LIB_DIR";
+
+
+/// Libraries providing the cpufreq_* functions
+#[derive(Clone, Copy)]
+enum Library {
+    Cpupower,
+    Cpufreq
+}
 
-    if status.is_ok() && status.unwrap().success() {
-        println!("cargo:rustc-link-lib=cpupower");
-        println!("cargo:rustc-cfg=cpufreq=\"cpupower\"");
-        return;
+
+impl Library {
+    /// Name given to the linker, also used as the value of `cfg(cpufreq)`
+    fn name(self) -> &'static str {
+        match self {
+            Library::Cpupower => "cpupower",
+            Library::Cpufreq => "cpufreq"
+        }
     }
 
-    status = Command::new("ld").arg("-lcpufreq").status();
+    /// Look for the library and emit the linker flags if found
+    fn find(self) -> bool {
+        if let Some(dir) = env::var_os(LIB_DIR_VARIABLE) {
+            return self.find_in(&PathBuf::from(dir));
+        }
+
+        // Emits the link flags itself on success
+        if pkg_config::Config::new().probe(&format!("lib{}", self.name())).is_ok() {
+            return true;
+        }
+
+        let found = Command::new("ld").arg(format!("-l{}", self.name())).output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
 
-    if status.is_ok() && status.unwrap().success() {
-        println!("cargo:rustc-link-lib=cpufreq");
-        println!("cargo:rustc-cfg=cpufreq=\"cpufreq\"");
-        return;
+        if found {
+            println!("cargo:rustc-link-lib={}", self.name());
+        }
+
+        found
     }
 
+    fn find_in(self, dir: &Path) -> bool {
+        let found = ["so", "a"].iter()
+            .any(|extension| dir.join(format!("lib{}.{}", self.name(), extension)).exists());
+
+        if found {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+            println!("cargo:rustc-link-lib={}", self.name());
+        }
+
+        found
+    }
+
+    fn link(self) {
+        println!("cargo:rustc-cfg=cpufreq=\"{}\"", self.name());
+    }
+
+    fn require(self, feature: &str) {
+        if !self.find() {
+            panic!("feature `{}` is enabled but lib{} was found neither by pkg-config nor by the linker. \
+                    Install it, point {} to the directory holding lib{}.so \
+                    or use the `native-sysfs` feature instead.",
+                   feature, self.name(), LIB_DIR_VARIABLE, self.name());
+        }
+
+        self.link();
+    }
+}
+
+
+fn feature_enabled(name: &str) -> bool {
+    env::var_os(format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"))).is_some()
+}
+
+
+fn use_sysfs() {
+    println!("cargo:rustc-cfg=cpufreq=\"sysfs\"");
+}
+
+
+fn use_dynamic() {
+    println!("cargo:rustc-cfg=cpufreq=\"dynamic\"");
+    println!("cargo:rustc-link-lib=dl");
+}
+
+
+fn use_mock() {
     println!("cargo:rustc-cfg=cpufreq=\"mock\"");
-    gcc::compile_library("libcpufreq.a", &["src/base/cpufreq.c", "src/base/sysfs.c"]);
+    cc::Build::new()
+        .file("src/base/cpufreq.c")
+        .file("src/base/sysfs.c")
+        .compile("libcpufreq.a");
+}
+
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(cpufreq, values(\"cpupower\", \"cpufreq\", \"dynamic\", \"mock\", \"sysfs\"))");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/base");
+    println!("cargo:rerun-if-env-changed={}", LIB_DIR_VARIABLE);
+
+    let enabled: Vec<&str> = BACKEND_FEATURES.iter().cloned().filter(|name| feature_enabled(name)).collect();
+
+    if enabled.len() > 1 {
+        panic!("features {} are mutually exclusive, enable only one of them",
+               enabled.iter().map(|name| format!("`{}`", name)).collect::<Vec<String>>().join(", "));
+    }
+
+    match enabled.first() {
+        Some(&"cpupower") => Library::Cpupower.require("cpupower"),
+        Some(&"cpufreq-legacy") => Library::Cpufreq.require("cpufreq-legacy"),
+        Some(&"dynamic") => use_dynamic(),
+        Some(&"mock") => use_mock(),
+        Some(&"native-sysfs") => use_sysfs(),
+        _ => {
+            match [Library::Cpupower, Library::Cpufreq].iter().find(|library| library.find()) {
+                Some(library) => library.link(),
+                None => {
+                    println!("cargo:warning=neither libcpupower nor libcpufreq found, \
+                              using sysfs directly (enable the `native-sysfs` feature to silence this)");
+                    use_sysfs();
+                }
+            }
+        }
+    }
 }
@@ -1,9 +1,8 @@
-extern crate errno;
-
 use std::str;
 use std::ffi::CStr;
 use std::string::String;
 use ::base::*;
+use ::libcpupower::{call, check_pointer};
 use ::types::*;
 use ::result::Result;
 use ::stat::Stat;
@@ -19,17 +18,7 @@ pub trait Extract<R> {
     fn get_value(current: *mut Self::Source) -> Result<R>;
 
     fn extract(id: CpuId) -> Result<Vec<R>> {
-        let list = Self::get_struct(id);
-
-        if list.is_null() {
-            let errno = errno::errno();
-
-            if errno.0 == 0 {
-                return Err(::error::CpuPowerError::Unknown);
-            } else {
-                return Err(::error::CpuPowerError::SystemError(errno::errno()));
-            }
-        }
+        let list = check_pointer(call(|| Self::get_struct(id)))?;
 
         let mut current = Self::get_first(list);
         let mut result = vec![];
@@ -39,15 +28,14 @@ pub trait Extract<R> {
                 break;
             }
 
-            let value = try!(Self::get_value(current));
-            result.push(R::from(value));
+            result.push(Self::get_value(current)?);
 
             current = Self::get_next(current);
         }
 
         Self::put_struct(list);
 
-        return Ok(result);
+        Ok(result)
     }
 }
 
@@ -57,7 +45,7 @@ impl Extract<String> for AvailableGovernors {
 
     fn get_struct(id: CpuId) -> *mut Self::Source {
         unsafe {
-            return cpufreq_get_available_governors(id);
+            cpufreq_get_available_governors(id)
         }
     }
 
@@ -85,12 +73,12 @@ impl Extract<String> for AvailableGovernors {
 
 
             if value.is_null() {
-                return Err(::error::CpuPowerError::SystemError(errno::errno()));
+                return Err(::error::CpuPowerError::Unknown);
             }
 
             str::from_utf8(CStr::from_ptr(value).to_bytes())
-                .and_then(|value| Ok(String::from(value)))
-                .or_else(|err| Err(From::from(err)))
+                .map(String::from)
+                .map_err(From::from)
         }
     }
 }
@@ -103,7 +91,7 @@ impl Extract<Frequency> for AvailableFrequencies {
 
     fn get_struct(id: CpuId) -> *mut Self::Source {
         unsafe {
-            return cpufreq_get_available_frequencies(id);
+            cpufreq_get_available_frequencies(id)
         }
     }
 
@@ -141,7 +129,7 @@ impl Extract<CpuId> for AffectedCpus {
 
     fn get_struct(id: CpuId) -> *mut Self::Source {
         unsafe {
-            return cpufreq_get_affected_cpus(id);
+            cpufreq_get_affected_cpus(id)
         }
     }
 
@@ -178,7 +166,7 @@ impl Extract<CpuId> for RelatedCpus {
 
     fn get_struct(id: CpuId) -> *mut Self::Source {
         unsafe {
-            return cpufreq_get_related_cpus(id);
+            cpufreq_get_related_cpus(id)
         }
     }
 
@@ -215,7 +203,7 @@ impl Extract<Stat> for Stats {
     fn get_struct(id: CpuId) -> *mut Self::Source {
         unsafe {
             let mut total_time: u64 = 0;
-            return cpufreq_get_stats(id, &mut total_time as *mut u64);
+            cpufreq_get_stats(id, &mut total_time as *mut u64)
         }
     }
 
@@ -0,0 +1,192 @@
+//! # amd-pstate driver
+//!
+//! `amd-pstate` (and `amd-pstate-epp` in active mode) drives AMD cpus
+//! through ACPI CPPC. Its operation mode is global, in
+//! `/sys/devices/system/cpu/amd_pstate/status`, while the performance
+//! levels of the cpus are read-only attributes of every policy.
+//! `AmdPstate` handles the former and `AmdPerf` holds the latter, see
+//! `Cpu::get_amd_perf` and `FreqPolicy::get_amd_perf`.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend, default_backend};
+use ::error::{CpuPowerError, Operation};
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+use ::types::Frequency;
+
+use std::fmt;
+use std::str::FromStr;
+use std::string::String;
+use std::sync::Arc;
+
+
+const DIRECTORY: &str = "amd_pstate";
+
+/// Attributes making up the performance levels
+pub const PERF_ATTRIBUTES: &str = "amd_pstate_highest_perf, amd_pstate_max_freq, amd_pstate_lowest_nonlinear_freq";
+
+
+/// Operation mode of the driver, from `amd_pstate/status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AmdPstateStatus {
+    /// `amd-pstate-epp`, the firmware picks the performance following the
+    /// energy performance preference
+    Active,
+    /// `amd-pstate` under the generic governors, which request a
+    /// performance level
+    Passive,
+    /// The governors set the limits, the firmware picks the performance
+    /// between them
+    Guided,
+    /// The driver is unregistered
+    Disabled
+}
+
+
+#[derive(Debug, Clone)]
+pub struct AmdPstate {
+    backend: Arc<dyn Backend>
+}
+
+
+/// CPPC performance levels of a cpu as exposed by `amd-pstate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmdPerf {
+    /// `amd_pstate_highest_perf`, abstract performance of the highest
+    /// boost frequency
+    pub highest_perf: u32,
+    /// `amd_pstate_max_freq`, highest frequency in kHz
+    pub max_freq: Frequency,
+    /// `amd_pstate_lowest_nonlinear_freq`, in kHz. Below it the power
+    /// saved isn't worth the performance lost.
+    pub lowest_nonlinear_freq: Frequency,
+    /// `amd_pstate_prefcore_ranking`, higher for the cores the firmware
+    /// prefers to run the work on. `None` without preferred core support.
+    pub prefcore_ranking: Option<u32>,
+    /// `amd_pstate_hw_prefcore`, whether the hardware supports preferred
+    /// cores
+    pub hw_prefcore: Option<bool>
+}
+
+
+impl AmdPstateStatus {
+    /// Name used by the kernel
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AmdPstateStatus::Active => "active",
+            AmdPstateStatus::Passive => "passive",
+            AmdPstateStatus::Guided => "guided",
+            AmdPstateStatus::Disabled => "disable"
+        }
+    }
+}
+
+
+impl fmt::Display for AmdPstateStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+impl FromStr for AmdPstateStatus {
+    type Err = CpuPowerError;
+
+    fn from_str(name: &str) -> Result<AmdPstateStatus> {
+        match name.trim() {
+            "active" => Ok(AmdPstateStatus::Active),
+            "passive" => Ok(AmdPstateStatus::Passive),
+            "guided" => Ok(AmdPstateStatus::Guided),
+            "disable" => Ok(AmdPstateStatus::Disabled),
+            _ => Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+        }
+    }
+}
+
+
+impl AmdPstate {
+    /// Driver of your system
+    pub fn new() -> AmdPstate {
+        AmdPstate::with_backend(default_backend())
+    }
+
+    /// Driver of the given sysfs tree
+    pub fn new_in(root: &SysfsRoot) -> AmdPstate {
+        AmdPstate::with_backend(Arc::new(root.clone()))
+    }
+
+    pub fn with_backend(backend: Arc<dyn Backend>) -> AmdPstate {
+        AmdPstate {
+            backend
+        }
+    }
+
+    /// Whether `amd-pstate` is there at all, even if disabled
+    pub fn is_available(&self) -> bool {
+        self.backend.list_directory(DIRECTORY).is_ok()
+    }
+
+    pub fn get_status(&self) -> Result<AmdPstateStatus> {
+        self.read("status", self.backend.read_attribute(&attribute("status")).and_then(|status| status.parse()))
+    }
+
+    /// Switch the driver to another mode, the policies are recreated and
+    /// lose their settings
+    pub fn set_status(&self, status: AmdPstateStatus) -> Result<()> {
+        let result = self.backend.write_attribute(&attribute("status"), status.name());
+        backend::check_global(Operation::Write, &attribute("status"), Some(status.to_string()), result)
+    }
+
+    /// Whether the scheduler favors the preferred cores, `amd_pstate/prefcore`
+    pub fn get_prefcore(&self) -> Result<bool> {
+        self.read("prefcore", self.backend.read_attribute(&attribute("prefcore")).and_then(|value| parse_enabled(&value)))
+    }
+
+    /// Wrap the error into `OperationFailed` telling which knob was read
+    fn read<T>(&self, name: &str, result: Result<T>) -> Result<T> {
+        backend::check_global(Operation::Read, &attribute(name), None, result)
+    }
+}
+
+
+impl Default for AmdPstate {
+    fn default() -> AmdPstate {
+        AmdPstate::new()
+    }
+}
+
+
+/// Performance levels from a cpufreq directory (`cpuN/cpufreq` or
+/// `cpufreq/policyN`), the preferred core ones being optional
+pub fn read_amd_perf<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<AmdPerf> {
+    let path = |name: &str| format!("{}/{}", directory, name);
+    let hw_prefcore = match backend.read_attribute(&path("amd_pstate_hw_prefcore")) {
+        Ok(value) => Some(parse_enabled(&value)?),
+        Err(_) => None
+    };
+
+    Ok(AmdPerf {
+        highest_perf: backend::read_value(backend, &path("amd_pstate_highest_perf"))?,
+        max_freq: backend::read_value(backend, &path("amd_pstate_max_freq"))?,
+        lowest_nonlinear_freq: backend::read_value(backend, &path("amd_pstate_lowest_nonlinear_freq"))?,
+        prefcore_ranking: backend::read_value(backend, &path("amd_pstate_prefcore_ranking")).ok(),
+        hw_prefcore
+    })
+}
+
+
+/// Parse `enabled`/`disabled`
+fn parse_enabled(value: &str) -> Result<bool> {
+    match value.trim() {
+        "enabled" => Ok(true),
+        "disabled" => Ok(false),
+        _ => Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+    }
+}
+
+
+fn attribute(name: &str) -> String {
+    format!("{}/{}", DIRECTORY, name)
+}
@@ -0,0 +1,627 @@
+//! # Abstraction over the ways to reach the cpufreq subsystem
+//!
+//! `Cpu` forwards every call to a `Backend`. The crate ships
+//! `LibCpupower` (when linked), `SysfsRoot`, `Fixture`, `InMemory` and
+//! `Simulator`, but any other implementation can be injected with
+//! `Cpu::with_backend`.
+//!
+//! A backend only has to provide access to the raw attributes found
+//! under `/sys/devices/system/cpu`: every cpufreq operation has a default
+//! implementation on top of them with the same semantics as the
+//! `sysfs_*` functions of libcpupower.
+//!
+//! Backends are `Send + Sync`: a `Cpu` can be moved to (or shared with)
+//! other threads, every call being independent from the others.
+
+extern crate errno;
+extern crate libc;
+
+use ::cpu_set::CpuSet;
+use ::error::{CpuPowerError, ErrorContext, Operation};
+use ::policy::Policy;
+use ::result::Result;
+use ::stat::Stat;
+use ::topology::CpuTopology;
+use ::types::{CpuId, CpuMask, Frequency};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+/// Governor names longer than that are rejected by the kernel
+const MAX_GOVERNOR_LEN: usize = 19;
+
+/// Attributes making up a policy
+pub const POLICY_ATTRIBUTES: &str = "scaling_min_freq, scaling_max_freq, scaling_governor";
+
+/// Entries of a cpufreq directory, for backends building it from a model
+pub const CPUFREQ_ATTRIBUTE_NAMES: [&str; 16] = [
+    "affected_cpus", "cpuinfo_cur_freq", "cpuinfo_max_freq", "cpuinfo_min_freq",
+    "cpuinfo_transition_latency", "related_cpus", "scaling_available_frequencies",
+    "scaling_available_governors", "scaling_cur_freq", "scaling_driver", "scaling_governor",
+    "scaling_max_freq", "scaling_min_freq", "scaling_setspeed", "stats/time_in_state", "stats/total_trans"
+];
+
+
+pub trait Backend: fmt::Debug + Send + Sync {
+    /// Read an attribute, `path` is relative to `/sys/devices/system/cpu`
+    /// (e.g. `cpu0/cpufreq/scaling_governor`). Trailing newline is stripped.
+    fn read_attribute(&self, path: &str) -> Result<String>;
+
+    /// Write an attribute, `path` is relative to `/sys/devices/system/cpu`
+    fn write_attribute(&self, path: &str, value: &str) -> Result<()>;
+
+    /// Names of the entries of a directory, sorted, `path` is relative to
+    /// `/sys/devices/system/cpu`
+    fn list_directory(&self, path: &str) -> Result<Vec<String>>;
+
+    /// Whether the cpu is present (it doesn't say whether it is online)
+    fn cpu_exists(&self, id: CpuId) -> bool {
+        self.list_directory(&format!("cpu{}", id)).is_ok()
+    }
+
+    /// Ids of the `cpuN` directories, sorted
+    fn list_cpus(&self) -> Result<CpuSet> {
+        Ok(self.list_directory("")?.iter().filter_map(|entry| parse_cpu_directory(entry)).collect())
+    }
+
+    /// Cpus of the mask. When the mask can't be read, possible and present
+    /// cpus are those listed by `list_cpus`, split by `is_online` for the
+    /// other masks.
+    fn get_cpus(&self, mask: CpuMask) -> Result<CpuSet> {
+        if let Ok(list) = self.read_attribute(mask.name()) {
+            return CpuSet::from_cpu_list(&list);
+        }
+
+        let mut result = CpuSet::new();
+
+        for id in self.list_cpus()? {
+            let selected = match mask {
+                CpuMask::Possible | CpuMask::Present => true,
+                CpuMask::Online => self.is_online(id)?,
+                CpuMask::Offline => !self.is_online(id)?
+            };
+
+            if selected {
+                result.insert(id);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Whether the cpu is running. Cpus without `cpuN/online` can't be
+    /// hot-unplugged and are always online.
+    fn is_online(&self, id: CpuId) -> Result<bool> {
+        match self.read_attribute(&online_attribute(id)) {
+            Ok(online) => parse_flag(&online),
+            Err(_) if self.cpu_exists(id) => Ok(true),
+            Err(_) => Err(CpuPowerError::CpuNotFound { id })
+        }
+    }
+
+    /// Position of the cpu read from `cpuN/topology`, which only exists
+    /// for online cpus
+    fn get_topology(&self, id: CpuId) -> Result<CpuTopology> {
+        let directory = format!("cpu{}/topology", id);
+
+        if self.list_directory(&directory).is_err() {
+            return Err(if !self.cpu_exists(id) {
+                CpuPowerError::CpuNotFound { id }
+            } else if !self.is_online(id)? {
+                CpuPowerError::CpuOffline { id, errno: errno::Errno(libc::ENOENT) }
+            } else {
+                CpuPowerError::from(errno::Errno(libc::ENOENT))
+            });
+        }
+
+        let read_id = |name: &str| -> Result<Option<u32>> {
+            match self.read_attribute(&format!("{}/{}", directory, name)) {
+                Ok(value) => Ok(u32::try_from(value.trim().parse::<i64>()?).ok()),
+                Err(_) => Ok(None)
+            }
+        };
+        let read_set = |names: &[&str]| -> Result<CpuSet> {
+            for name in names {
+                if let Ok(list) = self.read_attribute(&format!("{}/{}", directory, name)) {
+                    return CpuSet::from_cpu_list(&list);
+                }
+            }
+            Ok(CpuSet::new())
+        };
+
+        Ok(CpuTopology {
+            id,
+            package: read_id("physical_package_id")?,
+            die: read_id("die_id")?,
+            cluster: read_id("cluster_id")?,
+            core: read_id("core_id")?,
+            thread_siblings: read_set(&["thread_siblings_list"])?,
+            core_cpus: read_set(&["core_cpus_list", "thread_siblings_list"])?,
+            package_cpus: read_set(&["package_cpus_list", "core_siblings_list"])?
+        })
+    }
+
+    /// Relative performance of the cpu, from `cpuN/cpu_capacity`. The
+    /// biggest cpus of the system have 1024, the attribute only exists on
+    /// systems with asymmetric cpus.
+    fn get_capacity(&self, id: CpuId) -> Result<u64> {
+        read_value(self, &format!("cpu{}/cpu_capacity", id))
+    }
+
+    /// Whether the cpu can be taken offline, that is it has `cpuN/online`
+    fn is_hotpluggable(&self, id: CpuId) -> Result<bool> {
+        match self.read_attribute(&online_attribute(id)) {
+            Ok(_) => Ok(true),
+            Err(_) if self.cpu_exists(id) => Ok(false),
+            Err(_) => Err(CpuPowerError::CpuNotFound { id })
+        }
+    }
+
+    /// Bring the cpu online or take it offline. Bringing a cpu which can't
+    /// be hot-unplugged online succeeds without doing anything.
+    fn set_online(&self, id: CpuId, online: bool) -> Result<()> {
+        if !self.is_hotpluggable(id)? {
+            return if online { Ok(()) } else { Err(CpuPowerError::NotHotpluggable { id }) };
+        }
+
+        self.write_attribute(&online_attribute(id), if online { "1" } else { "0" })
+            .map_err(|error| match error.errno() {
+                Some(errno) if errno.0 == libc::EBUSY => CpuPowerError::HotplugRefused { id, online, errno },
+                _ => error
+            })
+    }
+
+    /// Frequency according to the kernel, in kHz
+    fn get_freq_kernel(&self, id: CpuId) -> Result<Frequency> {
+        read_value(self, &cpufreq_attribute(id, "scaling_cur_freq"))
+    }
+
+    /// Frequency according to the hardware, in kHz
+    fn get_freq_hardware(&self, id: CpuId) -> Result<Frequency> {
+        read_value(self, &cpufreq_attribute(id, "cpuinfo_cur_freq"))
+    }
+
+    /// Set the frequency of the cpu, in kHz.
+    /// Switches to the userspace governor first, like libcpupower does.
+    fn set_frequency(&self, id: CpuId, freq: Frequency) -> Result<()> {
+        let policy = self.get_policy(id)?;
+
+        if policy.governor != "userspace" {
+            self.modify_policy_governor(id, "userspace")?;
+        }
+
+        self.write_attribute(&cpufreq_attribute(id, "scaling_setspeed"), &freq.to_string())
+    }
+
+    /// Transition latency in nanoseconds
+    fn get_transition_latency(&self, id: CpuId) -> Result<u64> {
+        read_value(self, &cpufreq_attribute(id, "cpuinfo_transition_latency"))
+    }
+
+    /// Hardware frequency limits as (min, max), in kHz
+    fn get_hardware_limits(&self, id: CpuId) -> Result<(Frequency, Frequency)> {
+        let min = read_value(self, &cpufreq_attribute(id, "cpuinfo_min_freq"))?;
+        let max = read_value(self, &cpufreq_attribute(id, "cpuinfo_max_freq"))?;
+        Ok((min, max))
+    }
+
+    /// Name of the cpufreq driver
+    fn get_driver(&self, id: CpuId) -> Result<String> {
+        self.read_attribute(&cpufreq_attribute(id, "scaling_driver"))
+    }
+
+    fn get_policy(&self, id: CpuId) -> Result<Policy> {
+        read_policy(self, &cpufreq_directory(id))
+    }
+
+    /// Writes the limits in an order which never makes min greater than max
+    fn set_policy(&self, id: CpuId, policy: &Policy) -> Result<()> {
+        write_policy(self, &cpufreq_directory(id), policy)
+    }
+
+    fn modify_policy_min(&self, id: CpuId, min: Frequency) -> Result<()> {
+        self.write_attribute(&cpufreq_attribute(id, "scaling_min_freq"), &min.to_string())
+    }
+
+    fn modify_policy_max(&self, id: CpuId, max: Frequency) -> Result<()> {
+        self.write_attribute(&cpufreq_attribute(id, "scaling_max_freq"), &max.to_string())
+    }
+
+    fn modify_policy_governor(&self, id: CpuId, governor: &str) -> Result<()> {
+        write_governor(self, &cpufreq_directory(id), governor)
+    }
+
+    fn get_available_governors(&self, id: CpuId) -> Result<Vec<String>> {
+        read_words(self, &cpufreq_attribute(id, "scaling_available_governors"))
+    }
+
+    fn get_available_frequencies(&self, id: CpuId) -> Result<Vec<Frequency>> {
+        read_list(self, &cpufreq_attribute(id, "scaling_available_frequencies"))
+    }
+
+    /// Cpus which need their frequency coordinated by software
+    fn get_affected_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        read_cpu_set(self, &cpufreq_attribute(id, "affected_cpus"))
+    }
+
+    /// Cpus which share the frequency with this one, online or offline
+    fn get_related_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        read_cpu_set(self, &cpufreq_attribute(id, "related_cpus"))
+    }
+
+    fn get_stats(&self, id: CpuId) -> Result<Vec<Stat>> {
+        read_stats(self, &cpufreq_directory(id))
+    }
+
+    fn get_transitions(&self, id: CpuId) -> Result<u64> {
+        read_value(self, &cpufreq_attribute(id, "stats/total_trans"))
+    }
+}
+
+
+/// Implementation behind `Cpu::new` and `Cpu::get_all`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveBackend {
+    /// libcpupower or libcpufreq linked at build time
+    Linked,
+    /// Library loaded at runtime (`dynamic` feature), holds its file name
+    Loaded(&'static str),
+    /// `/sys/devices/system/cpu` accessed directly
+    Sysfs
+}
+
+
+/// Which backend `Cpu::new` uses. With the `dynamic` feature that's only
+/// known at runtime: sysfs is used when no library could be loaded.
+#[cfg(cpufreq = "sysfs")]
+pub fn active_backend() -> ActiveBackend {
+    ActiveBackend::Sysfs
+}
+
+#[cfg(cpufreq = "dynamic")]
+pub fn active_backend() -> ActiveBackend {
+    match ::base::load() {
+        Some(library) => ActiveBackend::Loaded(library),
+        None => ActiveBackend::Sysfs
+    }
+}
+
+#[cfg(not(any(cpufreq = "sysfs", cpufreq = "dynamic")))]
+pub fn active_backend() -> ActiveBackend {
+    ActiveBackend::Linked
+}
+
+
+pub fn default_backend() -> Arc<dyn Backend> {
+    match active_backend() {
+        #[cfg(not(cpufreq = "sysfs"))]
+        ActiveBackend::Linked | ActiveBackend::Loaded(_) => Arc::new(::libcpupower::LibCpupower),
+        _ => Arc::new(::sysfs::SysfsRoot::default())
+    }
+}
+
+
+/// Tell why an attribute of the cpu is missing: the cpu doesn't exist,
+/// it is offline or no cpufreq driver handles it. Other errors are
+/// returned untouched.
+pub fn explain_missing<B: Backend + ?Sized>(backend: &B, id: CpuId, error: CpuPowerError) -> CpuPowerError {
+    let errno = match error {
+        CpuPowerError::NotSupported{errno} if errno.0 == libc::ENOENT => errno,
+        error => return error
+    };
+
+    if !backend.cpu_exists(id) {
+        return CpuPowerError::CpuNotFound { id };
+    }
+
+    let offline = backend.read_attribute(&online_attribute(id))
+        .map(|online| online == "0")
+        .unwrap_or(false);
+
+    if offline {
+        CpuPowerError::CpuOffline { id, errno }
+    } else if backend.get_driver(id).is_err() {
+        CpuPowerError::DriverMissing { id, errno }
+    } else {
+        error
+    }
+}
+
+
+/// `InvalidValue` caused by a governor the cpu doesn't offer becomes `GovernorUnavailable`
+pub fn explain_governor<B: Backend + ?Sized>(backend: &B, id: CpuId, governor: &str, error: CpuPowerError) -> CpuPowerError {
+    match error {
+        CpuPowerError::InvalidValue{errno} => {
+            let available = backend.get_available_governors(id)
+                .map(|governors| governors.iter().any(|available| available == governor))
+                .unwrap_or(true);
+
+            if available {
+                error
+            } else {
+                CpuPowerError::GovernorUnavailable { governor: String::from(governor), errno }
+            }
+        },
+        error => error
+    }
+}
+
+
+/// Wrap the error of a global knob into `OperationFailed`, like `Cpu`
+/// does for the attributes of a cpu. `path` is relative to
+/// `/sys/devices/system/cpu`, e.g. `intel_pstate/no_turbo`.
+pub fn check_global<T>(operation: Operation, path: &str, value: Option<String>, result: Result<T>) -> Result<T> {
+    result.map_err(|error| CpuPowerError::OperationFailed {
+        context: ErrorContext {
+            cpu: None,
+            policy: None,
+            operation,
+            attribute: String::from(path),
+            value
+        },
+        source: Box::new(error)
+    })
+}
+
+
+/// Directory holding the cpufreq attributes of the cpu
+pub fn cpufreq_directory(id: CpuId) -> String {
+    format!("cpu{}/cpufreq", id)
+}
+
+
+/// Path of an attribute under `cpuN/cpufreq`
+pub fn cpufreq_attribute(id: CpuId, name: &str) -> String {
+    format!("{}/{}", cpufreq_directory(id), name)
+}
+
+
+/// Governor and limits from a cpufreq directory (`cpuN/cpufreq` or `cpufreq/policyN`)
+pub fn read_policy<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<Policy> {
+    let governor = backend.read_attribute(&format!("{}/scaling_governor", directory))?;
+    let min = read_value(backend, &format!("{}/scaling_min_freq", directory))?;
+    let max = read_value(backend, &format!("{}/scaling_max_freq", directory))?;
+    Ok(Policy::new(min, max, &governor))
+}
+
+
+/// Write the limits in an order which never makes min greater than max, then the governor
+pub fn write_policy<B: Backend + ?Sized>(backend: &B, directory: &str, policy: &Policy) -> Result<()> {
+    if policy.max < policy.min {
+        return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+    }
+
+    verify_governor(&policy.governor)?;
+
+    let min_path = format!("{}/scaling_min_freq", directory);
+    let max_path = format!("{}/scaling_max_freq", directory);
+
+    let old_min: Frequency = read_value(backend, &min_path).unwrap_or(0);
+    let write_max_first = old_min == 0 || policy.max >= old_min;
+
+    if write_max_first {
+        backend.write_attribute(&max_path, &policy.max.to_string())?;
+    }
+
+    backend.write_attribute(&min_path, &policy.min.to_string())?;
+
+    if !write_max_first {
+        backend.write_attribute(&max_path, &policy.max.to_string())?;
+    }
+
+    backend.write_attribute(&format!("{}/scaling_governor", directory), &policy.governor)
+}
+
+
+pub fn write_governor<B: Backend + ?Sized>(backend: &B, directory: &str, governor: &str) -> Result<()> {
+    verify_governor(governor)?;
+    backend.write_attribute(&format!("{}/scaling_governor", directory), governor)
+}
+
+
+/// `stats/time_in_state` of a cpufreq directory
+pub fn read_stats<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<Vec<Stat>> {
+    let content = backend.read_attribute(&format!("{}/stats/time_in_state", directory))?;
+    let mut result = Vec::new();
+
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+
+        match (fields.next(), fields.next()) {
+            (Some(freq), Some(time_in_state)) => result.push(Stat {
+                freq: freq.parse()?,
+                time_in_state: time_in_state.parse()?
+            }),
+            (None, None) => continue,
+            _ => return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+        }
+    }
+
+    Ok(result)
+}
+
+
+/// Read an attribute holding whitespace separated words
+pub fn read_words<B: Backend + ?Sized>(backend: &B, path: &str) -> Result<Vec<String>> {
+    Ok(backend.read_attribute(path)?.split_whitespace().map(String::from).collect())
+}
+
+
+/// Reverse of `cpufreq_attribute`: the cpu and the attribute name of a
+/// `cpuN/cpufreq/...` path
+pub fn parse_cpufreq_attribute(path: &str) -> Option<(CpuId, &str)> {
+    let mut parts = path.trim_matches('/').splitn(3, '/');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(cpu), Some("cpufreq"), Some(name)) if cpu.starts_with("cpu") => {
+            cpu[3..].parse().ok().map(|id| (id, name))
+        },
+        _ => None
+    }
+}
+
+
+/// Directory of a cpufreq policy, named after its first cpu
+pub fn policy_directory(policy: u32) -> String {
+    format!("cpufreq/policy{}", policy)
+}
+
+
+/// Reverse of `policy_directory`: the policy and the attribute name of a
+/// `cpufreq/policyN/...` path
+pub fn parse_policy_attribute(path: &str) -> Option<(u32, &str)> {
+    let mut parts = path.trim_matches('/').splitn(3, '/');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("cpufreq"), Some(policy), Some(name)) if policy.starts_with("policy") => {
+            policy[6..].parse().ok().map(|id| (id, name))
+        },
+        _ => None
+    }
+}
+
+
+/// Path of the hotplug switch of the cpu
+pub fn online_attribute(id: CpuId) -> String {
+    format!("cpu{}/online", id)
+}
+
+
+/// Id of a `cpuN` directory name
+pub fn parse_cpu_directory(name: &str) -> Option<CpuId> {
+    if name.len() > 3 && name.starts_with("cpu") && name[3..].bytes().all(|c| c.is_ascii_digit()) {
+        name[3..].parse().ok()
+    } else {
+        None
+    }
+}
+
+
+/// Parse the content of a `0`/`1` attribute
+pub fn parse_flag(value: &str) -> Result<bool> {
+    match value.trim() {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+    }
+}
+
+
+/// Parse a frequency written to an attribute, the kernel answers EINVAL
+/// to anything that doesn't parse
+pub fn parse_frequency(value: &str) -> Result<Frequency> {
+    value.trim().parse().map_err(|_| CpuPowerError::from(errno::Errno(libc::EINVAL)))
+}
+
+
+/// Space separated values, as in `related_cpus` or `scaling_available_governors`
+pub fn format_list<T: fmt::Display, I: IntoIterator<Item = T>>(values: I) -> String {
+    values.into_iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+
+/// Reverse of `read_stats`: one `<freq> <time>` line per frequency
+pub fn format_stats(stats: &[Stat]) -> String {
+    stats.iter()
+        .map(|stat| format!("{} {}", stat.freq, stat.time_in_state))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+/// `scaling_setspeed` only shows a frequency while the userspace governor is in charge
+pub fn format_setspeed(governor: &str, freq: Frequency) -> String {
+    if governor == "userspace" {
+        freq.to_string()
+    } else {
+        String::from("<unsupported>")
+    }
+}
+
+
+/// Entries of `path` in a flat set of attribute paths, for backends
+/// keeping attributes in a map rather than in a real directory tree
+pub fn list_children<'a, I>(paths: I, path: &str) -> Result<Vec<String>>
+    where I: IntoIterator<Item = &'a String>
+{
+    let path = path.trim_matches('/');
+    let mut result: Vec<String> = Vec::new();
+
+    for candidate in paths {
+        let rest = if path.is_empty() {
+            candidate.as_str()
+        } else if candidate.starts_with(path) && candidate[path.len()..].starts_with('/') {
+            &candidate[path.len() + 1..]
+        } else {
+            continue
+        };
+
+        if let Some(child) = rest.split('/').next() {
+            if !result.iter().any(|existing| existing == child) {
+                result.push(String::from(child));
+            }
+        }
+    }
+
+    if result.is_empty() {
+        return Err(CpuPowerError::from(errno::Errno(libc::ENOENT)));
+    }
+
+    result.sort();
+    Ok(result)
+}
+
+
+/// Read an attribute holding a single number
+pub fn read_value<B, T>(backend: &B, path: &str) -> Result<T>
+    where B: Backend + ?Sized, T: FromStr<Err = ParseIntError>
+{
+    Ok(backend.read_attribute(path)?.parse::<T>()?)
+}
+
+
+/// Read a `0`/`1` attribute
+pub fn read_flag<B: Backend + ?Sized>(backend: &B, path: &str) -> Result<bool> {
+    parse_flag(&backend.read_attribute(path)?)
+}
+
+
+/// Read an attribute holding a cpu list
+pub fn read_cpu_set<B: Backend + ?Sized>(backend: &B, path: &str) -> Result<CpuSet> {
+    CpuSet::from_cpu_list(&backend.read_attribute(path)?)
+}
+
+
+/// Read an attribute holding whitespace separated numbers
+pub fn read_list<B, T>(backend: &B, path: &str) -> Result<Vec<T>>
+    where B: Backend + ?Sized, T: FromStr<Err = ParseIntError>
+{
+    let content = backend.read_attribute(path)?;
+    let mut result = Vec::new();
+
+    for value in content.split_whitespace() {
+        result.push(value.parse::<T>()?);
+    }
+
+    Ok(result)
+}
+
+
+/// Same rules as `verify_gov` in `base/sysfs.c`
+fn verify_governor(governor: &str) -> Result<()> {
+    let valid = !governor.is_empty()
+        && governor.len() <= MAX_GOVERNOR_LEN
+        && governor.chars().all(|c| c.is_ascii_alphabetic() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+    }
+}
@@ -194,7 +194,7 @@ struct cpufreq_available_governors *cpufreq_get_available_governors(unsigned int
       continue;
     }
 
-    struct cpufreq_available_governors *next = malloc(sizeof(struct cpufreq_available_governors));
+    struct cpufreq_available_governors *next = calloc(1, sizeof(struct cpufreq_available_governors));
 
     if (!next) {
       cpufreq_put_available_governors(first);
@@ -0,0 +1,163 @@
+//! Functions of `base` resolved with `dlopen` at runtime instead of being
+//! linked, used with the `dynamic` feature.
+//!
+//! Each function keeps the signature of its C counterpart. Until the
+//! library is loaded they fail like the C function would, returning the
+//! error value with errno set to `ENOSYS`.
+
+extern crate errno;
+extern crate libc;
+
+use super::{Struct_cpufreq_affected_cpus, Struct_cpufreq_available_frequencies,
+            Struct_cpufreq_available_governors, Struct_cpufreq_policy, Struct_cpufreq_stats};
+
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+use std::sync::OnceLock;
+
+
+/// Tried in that order, the development symlink might not be installed
+pub const LIBRARY_NAMES: [&str; 5] = [
+    "libcpupower.so", "libcpupower.so.1", "libcpupower.so.0", "libcpufreq.so", "libcpufreq.so.0"
+];
+
+
+/// What a C function returns on failure
+trait Failure {
+    fn failure() -> Self;
+}
+
+impl Failure for libc::c_int {
+    fn failure() -> Self { -1 }
+}
+
+impl Failure for libc::c_ulong {
+    fn failure() -> Self { 0 }
+}
+
+impl Failure for () {
+    fn failure() -> Self {}
+}
+
+impl<T> Failure for *mut T {
+    fn failure() -> Self { ptr::null_mut() }
+}
+
+
+macro_rules! dynamic_functions {
+    ($($name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        /// Addresses of the library functions
+        struct Symbols {
+            $($name: unsafe extern "C" fn($($ty),*) -> $ret,)*
+        }
+
+        impl Symbols {
+            unsafe fn resolve(handle: *mut libc::c_void) -> Option<Symbols> {
+                Some(Symbols {
+                    $($name: {
+                        let address = libc::dlsym(handle, concat!(stringify!($name), "\0").as_ptr() as *const libc::c_char);
+
+                        if address.is_null() {
+                            return None;
+                        }
+
+                        mem::transmute::<*mut libc::c_void, unsafe extern "C" fn($($ty),*) -> $ret>(address)
+                    },)*
+                })
+            }
+        }
+
+        $(
+            pub unsafe fn $name($($arg: $ty),*) -> $ret {
+                match LIBRARY.get() {
+                    Some(&Some(ref library)) => (library.symbols.$name)($($arg),*),
+                    _ => {
+                        errno::set_errno(errno::Errno(libc::ENOSYS));
+                        Failure::failure()
+                    }
+                }
+            }
+        )*
+    }
+}
+
+
+dynamic_functions! {
+    cpufreq_cpu_exists(cpu: libc::c_uint) -> libc::c_int;
+    cpufreq_get_freq_kernel(cpu: libc::c_uint) -> libc::c_ulong;
+    cpufreq_get_freq_hardware(cpu: libc::c_uint) -> libc::c_ulong;
+    cpufreq_get_transition_latency(cpu: libc::c_uint) -> libc::c_ulong;
+    cpufreq_get_hardware_limits(cpu: libc::c_uint, min: *mut libc::c_ulong, max: *mut libc::c_ulong) -> libc::c_int;
+    cpufreq_get_driver(cpu: libc::c_uint) -> *mut libc::c_char;
+    cpufreq_put_driver(ptr: *mut libc::c_char) -> ();
+    cpufreq_get_policy(cpu: libc::c_uint) -> *mut Struct_cpufreq_policy;
+    cpufreq_put_policy(policy: *mut Struct_cpufreq_policy) -> ();
+    cpufreq_get_available_governors(cpu: libc::c_uint) -> *mut Struct_cpufreq_available_governors;
+    cpufreq_put_available_governors(first: *mut Struct_cpufreq_available_governors) -> ();
+    cpufreq_get_available_frequencies(cpu: libc::c_uint) -> *mut Struct_cpufreq_available_frequencies;
+    cpufreq_put_available_frequencies(first: *mut Struct_cpufreq_available_frequencies) -> ();
+    cpufreq_get_affected_cpus(cpu: libc::c_uint) -> *mut Struct_cpufreq_affected_cpus;
+    cpufreq_put_affected_cpus(first: *mut Struct_cpufreq_affected_cpus) -> ();
+    cpufreq_get_related_cpus(cpu: libc::c_uint) -> *mut Struct_cpufreq_affected_cpus;
+    cpufreq_put_related_cpus(first: *mut Struct_cpufreq_affected_cpus) -> ();
+    cpufreq_get_stats(cpu: libc::c_uint, total_time: *mut libc::c_ulonglong) -> *mut Struct_cpufreq_stats;
+    cpufreq_put_stats(stats: *mut Struct_cpufreq_stats) -> ();
+    cpufreq_get_transitions(cpu: libc::c_uint) -> libc::c_ulong;
+    cpufreq_set_policy(cpu: libc::c_uint, policy: *mut Struct_cpufreq_policy) -> libc::c_int;
+    cpufreq_modify_policy_min(cpu: libc::c_uint, min_freq: libc::c_ulong) -> libc::c_int;
+    cpufreq_modify_policy_max(cpu: libc::c_uint, max_freq: libc::c_ulong) -> libc::c_int;
+    cpufreq_modify_policy_governor(cpu: libc::c_uint, governor: *mut libc::c_char) -> libc::c_int;
+    cpufreq_set_frequency(cpu: libc::c_uint, target_frequency: libc::c_ulong) -> libc::c_int;
+}
+
+
+/// The library is never unloaded, the handle is kept for the process lifetime
+struct Library {
+    name: &'static str,
+    symbols: Symbols
+}
+
+static LIBRARY: OnceLock<Option<Library>> = OnceLock::new();
+
+
+/// Load the first library providing every function, only tried once.
+/// Returns the name of the loaded library.
+pub fn load() -> Option<&'static str> {
+    LIBRARY.get_or_init(|| open_first(&LIBRARY_NAMES))
+        .as_ref()
+        .map(|library| library.name)
+}
+
+
+/// Name of the library `load` would pick among `names`
+#[cfg(test)]
+pub fn first_loadable(names: &[&'static str]) -> Option<&'static str> {
+    open_first(names).map(|library| library.name)
+}
+
+
+fn open_first(names: &[&'static str]) -> Option<Library> {
+    names.iter().filter_map(|name| open(name)).next()
+}
+
+
+fn open(name: &'static str) -> Option<Library> {
+    let file_name = CString::new(name).ok()?;
+
+    unsafe {
+        let handle = libc::dlopen(file_name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+
+        if handle.is_null() {
+            return None;
+        }
+
+        match Symbols::resolve(handle) {
+            Some(symbols) => Some(Library { name, symbols }),
+            None => {
+                libc::dlclose(handle);
+                None
+            }
+        }
+    }
+}
@@ -70,6 +70,12 @@ impl ::std::default::Default for Struct_cpufreq_stats {
     fn default() -> Self { unsafe { ::std::mem::zeroed() } }
 }
 
+#[cfg(cpufreq = "dynamic")]
+mod dynamic;
+#[cfg(cpufreq = "dynamic")]
+pub use self::dynamic::*;
+
+#[cfg(not(cpufreq = "dynamic"))]
 extern "C" {
     pub fn cpufreq_cpu_exists(cpu: libc::c_uint) -> libc::c_int;
     pub fn cpufreq_get_freq_kernel(cpu: libc::c_uint) -> libc::c_ulong;
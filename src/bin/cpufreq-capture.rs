@@ -0,0 +1,64 @@
+//! Record the cpufreq attributes of this machine into a fixture.
+//!
+//! ```text
+//! cpufreq-capture [--root DIR] OUTPUT
+//! ```
+//!
+//! OUTPUT ending with `.json` gets a single JSON file, anything else
+//! becomes a directory laid out like `/sys/devices/system/cpu`.
+
+extern crate cpufreq;
+
+use cpufreq::{Fixture, SysfsRoot};
+
+use std::env;
+use std::process;
+
+
+const USAGE: &str = "Usage: cpufreq-capture [--root DIR] OUTPUT";
+
+
+fn main() {
+    let mut root = SysfsRoot::default();
+    let mut output = None;
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--root" => match args.next() {
+                Some(path) => root = SysfsRoot::new(path),
+                None => exit_with_usage()
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if output.is_none() && !arg.starts_with('-') => output = Some(arg),
+            _ => exit_with_usage()
+        }
+    }
+
+    let output = output.unwrap_or_else(|| exit_with_usage());
+
+    let result = Fixture::capture(&root).and_then(|fixture| {
+        if output.ends_with(".json") {
+            fixture.save_json(&output)
+        } else {
+            fixture.save_dir(&output)
+        }.map(|_| fixture.attributes().len())
+    });
+
+    match result {
+        Ok(count) => println!("Captured {} attributes from {} into {}", count, root.path().display(), output),
+        Err(error) => {
+            eprintln!("Can't capture {}: {}", root.path().display(), error);
+            process::exit(1);
+        }
+    }
+}
+
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,204 @@
+//! # Turbo frequencies
+//!
+//! Depending on the driver and the kernel, turbo (boost) frequencies are
+//! switched on and off in a different place: the global `cpufreq/boost`,
+//! `intel_pstate/no_turbo` (inverted), the `boost` attribute of every
+//! policy or, on older AMD systems, their `cpb` attribute. `Boost` finds
+//! the one in use and hides the differences.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend, default_backend};
+use ::error::{CpuPowerError, ErrorContext, Operation};
+use ::freq_policy::FreqPolicy;
+use ::intel_pstate::IntelPstate;
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+
+use std::fmt;
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+/// Where boost is switched, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BoostMechanism {
+    /// `cpufreq/boost`, e.g. `acpi-cpufreq` and `amd-pstate`
+    Global,
+    /// `intel_pstate/no_turbo`
+    IntelNoTurbo,
+    /// `cpufreq/policyN/boost`
+    PerPolicy,
+    /// `cpufreq/policyN/cpb`, `acpi-cpufreq` on older AMD cpus
+    AmdCpb
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Boost {
+    mechanism: BoostMechanism,
+    backend: Arc<dyn Backend>
+}
+
+
+impl BoostMechanism {
+    /// Attribute switching boost, relative to `/sys/devices/system/cpu`
+    /// (N standing for every policy)
+    pub fn attribute(&self) -> &'static str {
+        match *self {
+            BoostMechanism::Global => "cpufreq/boost",
+            BoostMechanism::IntelNoTurbo => "intel_pstate/no_turbo",
+            BoostMechanism::PerPolicy => "cpufreq/policyN/boost",
+            BoostMechanism::AmdCpb => "cpufreq/policyN/cpb"
+        }
+    }
+
+    /// Whether each policy has its own switch
+    pub fn is_per_policy(&self) -> bool {
+        match *self {
+            BoostMechanism::PerPolicy | BoostMechanism::AmdCpb => true,
+            BoostMechanism::Global | BoostMechanism::IntelNoTurbo => false
+        }
+    }
+}
+
+
+impl fmt::Display for BoostMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.attribute())
+    }
+}
+
+
+impl Boost {
+    /// Boost control of your system
+    pub fn detect() -> Result<Boost> {
+        Boost::detect_with(default_backend())
+    }
+
+    /// Boost control of the given sysfs tree
+    pub fn detect_in(root: &SysfsRoot) -> Result<Boost> {
+        Boost::detect_with(Arc::new(root.clone()))
+    }
+
+    /// Boost control of the given backend, `NotSupported` if there is none
+    pub fn detect_with(backend: Arc<dyn Backend>) -> Result<Boost> {
+        let policies = FreqPolicy::get_all_with(backend.clone()).unwrap_or_default();
+        let policy_has = |name: &str| policies.iter().any(|policy| backend.read_attribute(&policy_attribute(policy, name)).is_ok());
+
+        let mechanism = if backend.read_attribute("cpufreq/boost").is_ok() {
+            BoostMechanism::Global
+        } else if backend.read_attribute("intel_pstate/no_turbo").is_ok() {
+            BoostMechanism::IntelNoTurbo
+        } else if policy_has("boost") {
+            BoostMechanism::PerPolicy
+        } else if policy_has("cpb") {
+            BoostMechanism::AmdCpb
+        } else {
+            return Err(CpuPowerError::from(errno::Errno(libc::ENOENT)));
+        };
+
+        Ok(Boost { mechanism, backend })
+    }
+
+    /// Attribute used to switch boost
+    pub fn get_mechanism(&self) -> BoostMechanism {
+        self.mechanism
+    }
+
+    /// Whether boost is enabled, on at least one policy for the per policy
+    /// mechanisms
+    pub fn is_enabled(&self) -> Result<bool> {
+        match self.mechanism {
+            BoostMechanism::Global => {
+                let result = backend::read_flag(&*self.backend, "cpufreq/boost");
+                backend::check_global(Operation::Read, "cpufreq/boost", None, result)
+            },
+            BoostMechanism::IntelNoTurbo => Ok(!self.intel_pstate().get_no_turbo()?),
+            BoostMechanism::PerPolicy | BoostMechanism::AmdCpb => {
+                for policy in self.policies()? {
+                    if self.is_enabled_for(&policy)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Enable or disable boost for every cpu
+    pub fn set_enabled(&self, enabled: bool) -> Result<()> {
+        match self.mechanism {
+            BoostMechanism::Global => {
+                let result = self.backend.write_attribute("cpufreq/boost", flag(enabled));
+                backend::check_global(Operation::Write, "cpufreq/boost", Some(String::from(flag(enabled))), result)
+            },
+            BoostMechanism::IntelNoTurbo => self.intel_pstate().set_no_turbo(!enabled),
+            BoostMechanism::PerPolicy | BoostMechanism::AmdCpb => {
+                for policy in self.policies()? {
+                    self.set_enabled_for(&policy, enabled)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether boost is enabled for the policy, from its `boost` (or `cpb`)
+    /// attribute whatever the mechanism in use
+    pub fn is_enabled_for(&self, policy: &FreqPolicy) -> Result<bool> {
+        let result = self.switch_of(policy).and_then(|path| backend::read_flag(&*self.backend, &path));
+        self.check(policy, Operation::Read, None, result)
+    }
+
+    /// Enable or disable boost for the policy only. With a global
+    /// mechanism the global switch has to be enabled too.
+    pub fn set_enabled_for(&self, policy: &FreqPolicy, enabled: bool) -> Result<()> {
+        let result = self.switch_of(policy).and_then(|path| self.backend.write_attribute(&path, flag(enabled)));
+        self.check(policy, Operation::Write, Some(String::from(flag(enabled))), result)
+    }
+
+    fn policies(&self) -> Result<Vec<FreqPolicy>> {
+        FreqPolicy::get_all_with(self.backend.clone())
+    }
+
+    fn intel_pstate(&self) -> IntelPstate {
+        IntelPstate::with_backend(self.backend.clone())
+    }
+
+    /// Wrap the error of a policy switch into `OperationFailed`, like
+    /// `FreqPolicy` does
+    fn check<T>(&self, policy: &FreqPolicy, operation: Operation, value: Option<String>, result: Result<T>) -> Result<T> {
+        result.map_err(|error| CpuPowerError::OperationFailed {
+            context: ErrorContext {
+                cpu: None,
+                policy: Some(policy.get_id()),
+                operation,
+                attribute: String::from(if self.mechanism == BoostMechanism::AmdCpb { "cpb" } else { "boost" }),
+                value
+            },
+            source: Box::new(error)
+        })
+    }
+
+    /// `boost` or `cpb` of the policy, `NotSupported` without either
+    fn switch_of(&self, policy: &FreqPolicy) -> Result<String> {
+        let names = if self.mechanism == BoostMechanism::AmdCpb { ["cpb", "boost"] } else { ["boost", "cpb"] };
+
+        names.iter()
+            .map(|name| policy_attribute(policy, name))
+            .find(|path| self.backend.read_attribute(path).is_ok())
+            .ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+}
+
+
+fn policy_attribute(policy: &FreqPolicy, name: &str) -> String {
+    format!("{}/{}", backend::policy_directory(policy.get_id()), name)
+}
+
+
+fn flag(enabled: bool) -> &'static str {
+    if enabled { "1" } else { "0" }
+}
@@ -0,0 +1,218 @@
+//! # Operations on many cpus at once
+//!
+//! `Bulk` applies an operation to a set of cpus. Cpus sharing a policy
+//! share its attributes, so the operation runs once per policy (on its
+//! lowest cpu of the set) and the policies are handled in parallel.
+//! Failures don't stop the other policies: every cpu gets its result in
+//! the returned `Report`.
+
+use ::backend::{Backend, default_backend};
+use ::cpu::Cpu;
+use ::cpu_set::CpuSet;
+use ::epp::Epp;
+use ::error::CpuPowerError;
+use ::policy::Policy;
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+use ::types::{CpuId, CpuMask, Frequency};
+
+use std::string::String;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::vec::Vec;
+
+
+#[derive(Debug, Clone)]
+pub struct Bulk {
+    cpus: CpuSet,
+    backend: Arc<dyn Backend>
+}
+
+
+/// Outcome of a bulk operation, one result per policy shared by the cpus
+/// of the set belonging to it
+#[derive(Debug)]
+pub struct Report<T> {
+    groups: Vec<(CpuSet, Result<T>)>
+}
+
+
+impl Bulk {
+    /// Every present cpu of your system
+    pub fn all() -> Bulk {
+        Bulk::all_with(default_backend())
+    }
+
+    /// Every present cpu known to the given backend
+    pub fn all_with(backend: Arc<dyn Backend>) -> Bulk {
+        let cpus = backend.get_cpus(CpuMask::Present).unwrap_or_default();
+        Bulk::with_backend(backend, cpus)
+    }
+
+    pub fn new(cpus: CpuSet) -> Bulk {
+        Bulk::with_backend(default_backend(), cpus)
+    }
+
+    /// Cpus of the given sysfs tree
+    pub fn new_in(root: &SysfsRoot, cpus: CpuSet) -> Bulk {
+        Bulk::with_backend(Arc::new(root.clone()), cpus)
+    }
+
+    pub fn with_backend(backend: Arc<dyn Backend>, cpus: CpuSet) -> Bulk {
+        Bulk {
+            cpus,
+            backend
+        }
+    }
+
+    pub fn get_cpus(&self) -> &CpuSet {
+        &self.cpus
+    }
+
+    pub fn set_governor(&self, governor: &str) -> Report<()> {
+        self.run(|cpu| cpu.modify_policy_governor(governor))
+    }
+
+    /// Change both limits, see `Cpu::set_limits`
+    pub fn set_limits(&self, min: Frequency, max: Frequency) -> Report<()> {
+        self.run(|cpu| cpu.set_limits(min, max))
+    }
+
+    pub fn set_policy(&self, policy: &Policy) -> Report<()> {
+        self.run(|cpu| cpu.set_policy(policy))
+    }
+
+    /// Energy performance preference of every policy, see `Cpu::set_epp`
+    pub fn set_epp<E: Into<Epp>>(&self, epp: E) -> Report<()> {
+        let epp = epp.into();
+        self.run(|cpu| cpu.set_epp(epp.clone()))
+    }
+
+    /// Frequency of each policy, see `Cpu::get_freq`
+    pub fn get_freq(&self) -> Report<Frequency> {
+        self.run(Cpu::get_freq)
+    }
+
+    /// Run any operation once per policy
+    pub fn run<T, F>(&self, operation: F) -> Report<T>
+        where T: Send, F: Fn(&Cpu) -> Result<T> + Sync
+    {
+        let groups = self.group_by_policy();
+        let results = parallel(&groups, |group| {
+            // Groups are never empty
+            operation(&Cpu::with_backend(self.backend.clone(), group.first().unwrap()))
+        });
+
+        Report { groups: groups.into_iter().zip(results).collect() }
+    }
+
+    /// Split the set by policy. Cpus whose related cpus can't be read
+    /// (e.g. offline ones) are on their own.
+    fn group_by_policy(&self) -> Vec<CpuSet> {
+        let mut remaining = self.cpus.clone();
+        let mut groups = Vec::new();
+
+        while let Some(id) = remaining.first() {
+            let mut group = self.backend.get_related_cpus(id)
+                .map(|related| related.intersection(&remaining))
+                .unwrap_or_default();
+            group.insert(id);
+
+            remaining = remaining.difference(&group);
+            groups.push(group);
+        }
+
+        groups
+    }
+}
+
+
+impl<T> Report<T> {
+    /// Result for the cpu, `None` if it was not part of the operation
+    pub fn get(&self, id: CpuId) -> Option<&Result<T>> {
+        self.groups.iter().find(|group| group.0.contains(id)).map(|group| &group.1)
+    }
+
+    /// Result of every cpu, ordered by cpu
+    pub fn iter(&self) -> impl Iterator<Item = (CpuId, &Result<T>)> {
+        let mut results: Vec<(CpuId, &Result<T>)> = self.groups.iter()
+            .flat_map(|group| group.0.iter().map(move |id| (id, &group.1)))
+            .collect();
+        results.sort_by_key(|result| result.0);
+        results.into_iter()
+    }
+
+    /// Cpus sharing a policy with the result of the operation on it
+    pub fn groups(&self) -> &[(CpuSet, Result<T>)] {
+        &self.groups
+    }
+
+    pub fn succeeded(&self) -> CpuSet {
+        self.select(|result| result.is_ok())
+    }
+
+    pub fn failed(&self) -> CpuSet {
+        self.select(|result| result.is_err())
+    }
+
+    /// Errors along with the cpus they happened for
+    pub fn errors(&self) -> impl Iterator<Item = (&CpuSet, &CpuPowerError)> {
+        self.groups.iter().filter_map(|group| group.1.as_ref().err().map(|error| (&group.0, error)))
+    }
+
+    /// Whether the operation succeeded for every cpu
+    pub fn is_ok(&self) -> bool {
+        self.groups.iter().all(|group| group.1.is_ok())
+    }
+
+    /// Human readable list of the failures, one line per policy
+    pub fn describe_errors(&self) -> String {
+        self.errors()
+            .map(|(cpus, error)| format!("cpus {}: {}", cpus, error))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn select<F: Fn(&Result<T>) -> bool>(&self, filter: F) -> CpuSet {
+        self.groups.iter()
+            .filter(|group| filter(&group.1))
+            .flat_map(|group| group.0.iter())
+            .collect()
+    }
+}
+
+
+/// Map the items on a few threads, keeping their order
+fn parallel<I, T, F>(items: &[I], function: F) -> Vec<T>
+    where I: Sync, T: Send, F: Fn(&I) -> T + Sync
+{
+    let workers = thread::available_parallelism().map(|count| count.get()).unwrap_or(1).min(items.len());
+
+    if workers <= 1 {
+        return items.iter().map(function).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<T>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+
+                if index >= items.len() {
+                    break;
+                }
+
+                let result = function(&items[index]);
+                results.lock().unwrap_or_else(|error| error.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().unwrap_or_else(|error| error.into_inner())
+        .into_iter()
+        .map(|result| result.expect("every item is handled by a worker"))
+        .collect()
+}
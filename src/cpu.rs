@@ -1,75 +1,212 @@
-///! # The main object for cpupower library's documentation
-///!
+//! # The main object for cpupower library's documentation
+//!
 
 extern crate errno;
 extern crate libc;
 
-use ::base::*;
+use ::amd_pstate::{self, AmdPerf};
 use ::policy::*;
 use ::stat::*;
+use ::cpu_set::CpuSet;
+use ::energy_perf_bias::{self, EnergyPerfBias};
+use ::epp::{self, Epp};
+use ::error::{CpuPowerError, ErrorContext, Operation};
+use ::freq_policy::FreqPolicy;
 use ::result::Result;
-use ::types::{CpuId, Frequency};
-use ::adapters::Extract;
+use ::setspeed::{self, SetSpeed};
+use ::backend::{Backend, POLICY_ATTRIBUTES, cpufreq_directory, default_backend, explain_governor, explain_missing};
+use ::sysfs::SysfsRoot;
+use ::topology::CpuTopology;
+use ::tunables::GovernorTunables;
+use ::types::{CpuId, CpuMask, Frequency};
 
-use std::os::raw::c_char;
-use std::ffi::{CStr, CString};
 use std::iter;
-use std::str;
+use std::sync::Arc;
 use std::string::String;
+use std::collections::btree_set;
 use std::vec::Vec;
 use std::fmt;
 
 
 pub struct Iterator {
-    next_id: CpuId,
+    ids: btree_set::IntoIter<CpuId>,
+    backend: Arc<dyn Backend>
 }
 
 
-/// Iterate over all cpus
+/// Iterate over a set of cpus
 impl iter::Iterator for Iterator {
     type Item = Cpu;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let current_id = self.next_id;
-        self.next_id += 1;
+        self.ids.next().map(|id| Cpu::with_backend(self.backend.clone(), id))
+    }
 
-        match Cpu::exists(current_id) {
-            true => Some(Cpu::new(current_id)),
-            _ => None
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
     }
 }
 
 
-#[derive(Debug)]
+/// A cpu handle, cheap to clone. It is `Send + Sync`, so clones (or
+/// references) can be used from any number of threads at once.
+#[derive(Debug, Clone)]
 pub struct Cpu {
     /// Id of current cpu
     /// Usually cpu id's starts from 0
-    id: CpuId
+    id: CpuId,
+    /// Backend all the calls are forwarded to
+    backend: Arc<dyn Backend>
 }
 
 
 impl Cpu {
     /// Iterate over all Cpu's available in your system
     pub fn get_all() -> Iterator {
+        Cpu::get_all_with(default_backend())
+    }
+
+    /// Iterate over all Cpu's found in the given sysfs tree
+    pub fn get_all_in(root: &SysfsRoot) -> Iterator {
+        Cpu::get_all_with(Arc::new(root.clone()))
+    }
+
+    /// Iterate over all Cpu's known to the given backend, that is the
+    /// present ones whether online or not
+    pub fn get_all_with(backend: Arc<dyn Backend>) -> Iterator {
+        Cpu::get_mask_with(backend, CpuMask::Present)
+    }
+
+    /// Cpus which may ever be plugged in your system
+    pub fn get_possible() -> Iterator {
+        Cpu::get_mask_with(default_backend(), CpuMask::Possible)
+    }
+
+    /// Cpus physically present in your system
+    pub fn get_present() -> Iterator {
+        Cpu::get_mask_with(default_backend(), CpuMask::Present)
+    }
+
+    /// Cpus running in your system
+    pub fn get_online() -> Iterator {
+        Cpu::get_mask_with(default_backend(), CpuMask::Online)
+    }
+
+    /// Possible cpus of your system which are not running
+    pub fn get_offline() -> Iterator {
+        Cpu::get_mask_with(default_backend(), CpuMask::Offline)
+    }
+
+    /// Iterate over the cpus of a mask of the given sysfs tree
+    pub fn get_mask_in(root: &SysfsRoot, mask: CpuMask) -> Iterator {
+        Cpu::get_mask_with(Arc::new(root.clone()), mask)
+    }
+
+    /// Iterate over the cpus of a mask according to the given backend.
+    /// Nothing is returned if it can't be determined.
+    pub fn get_mask_with(backend: Arc<dyn Backend>, mask: CpuMask) -> Iterator {
         Iterator {
-            next_id: 0
+            ids: backend.get_cpus(mask).unwrap_or_default().into_iter(),
+            backend
         }
     }
 
+    /// Present cpus of your system which can't be taken offline
+    pub fn get_not_hotpluggable() -> Result<CpuSet> {
+        Cpu::get_not_hotpluggable_with(default_backend())
+    }
+
+    /// Present cpus of the given sysfs tree which can't be taken offline
+    pub fn get_not_hotpluggable_in(root: &SysfsRoot) -> Result<CpuSet> {
+        Cpu::get_not_hotpluggable_with(Arc::new(root.clone()))
+    }
+
+    /// Present cpus which can't be taken offline according to the given backend
+    pub fn get_not_hotpluggable_with(backend: Arc<dyn Backend>) -> Result<CpuSet> {
+        let mut result = CpuSet::new();
+
+        for id in backend.get_cpus(CpuMask::Present)? {
+            if !backend.is_hotpluggable(id)? {
+                result.insert(id);
+            }
+        }
+
+        Ok(result)
+    }
+
     /// Check whether a Cpu with given ID exists in you system
     pub fn exists(id: CpuId) -> bool {
-        unsafe {
-            cpufreq_cpu_exists(id as u32) == 0
-        }
+        default_backend().cpu_exists(id)
+    }
+
+    /// Check whether a Cpu with given ID exists in the given sysfs tree
+    pub fn exists_in(root: &SysfsRoot, id: CpuId) -> bool {
+        root.cpu_exists(id)
     }
 
     pub fn new(id: CpuId) -> Cpu {
+        Cpu::with_backend(default_backend(), id)
+    }
+
+    /// Create a Cpu which reads and writes the given sysfs tree
+    /// instead of the one used by default
+    pub fn new_in(root: &SysfsRoot, id: CpuId) -> Cpu {
+        Cpu::with_backend(Arc::new(root.clone()), id)
+    }
+
+    /// Create a Cpu forwarding all the calls to the given backend
+    pub fn with_backend(backend: Arc<dyn Backend>, id: CpuId) -> Cpu {
         Cpu {
-            id: id
+            id,
+            backend
         }
     }
 
+    /// Backend this Cpu forwards the calls to
+    pub fn get_backend(&self) -> &Arc<dyn Backend> {
+        &self.backend
+    }
+
+    /// Wrap the error into `OperationFailed` telling what was attempted
+    fn check<T>(&self, operation: Operation, attribute: &str, value: Option<String>, result: Result<T>) -> Result<T> {
+        result.map_err(|error| CpuPowerError::OperationFailed {
+            context: ErrorContext {
+                cpu: Some(self.id),
+                policy: None,
+                operation,
+                attribute: String::from(attribute),
+                value
+            },
+            source: Box::new(error)
+        })
+    }
+
+    /// A missing attribute under `cpuN/cpufreq` becomes `CpuNotFound`,
+    /// `CpuOffline` or `DriverMissing` when that's the reason
+    fn explain<T>(&self, result: Result<T>) -> Result<T> {
+        result.map_err(|error| explain_missing(&*self.backend, self.id, error))
+    }
+
+    /// Check the result of reading an attribute under `cpuN/cpufreq`
+    fn read<T>(&self, attribute: &str, result: Result<T>) -> Result<T> {
+        self.check(Operation::Read, attribute, None, self.explain(result))
+    }
+
+    /// Check the result of writing an attribute under `cpuN/cpufreq`
+    fn write<T, V: fmt::Display>(&self, attribute: &str, value: V, result: Result<T>) -> Result<T> {
+        self.check(Operation::Write, attribute, Some(value.to_string()), self.explain(result))
+    }
+
+    /// Check the result of reading an attribute of `cpuN` outside of
+    /// cpufreq, e.g. `topology`. Whether cpufreq works doesn't matter.
+    fn read_device<T>(&self, attribute: &str, result: Result<T>) -> Result<T> {
+        self.check(Operation::Read, attribute, None, result)
+    }
+
+    fn write_device<T, V: fmt::Display>(&self, attribute: &str, value: V, result: Result<T>) -> Result<T> {
+        self.check(Operation::Write, attribute, Some(value.to_string()), result)
+    }
+
     /// Get frequency reported by hardware or by kernel
     /// This function tries to get freq using call to hardware first,
     /// and if that call fails - uses call to kernel
@@ -81,85 +218,77 @@ impl Cpu {
     /// According to the underlying library documentation -
     /// you don't need to be root to perform this operation
     pub fn get_freq_kernel(&self) -> Result<Frequency> {
-        unsafe {
-            let frequency = cpufreq_get_freq_kernel(self.id as u32);
-            match frequency {
-                0 => Err(::error::CpuPowerError::SystemError(errno::errno())),
-                _ => Ok(frequency)
-            }
-        }
+        self.read("scaling_cur_freq", self.backend.get_freq_kernel(self.id))
     }
 
     /// Get frequency reported by your hardware
     /// According to the underlying library documentation -
     /// you should be root to perform this operation
     pub fn get_freq_hardware(&self) -> Result<Frequency> {
-        unsafe {
-            let frequency = cpufreq_get_freq_hardware(self.id as u32);
-            match frequency {
-                0 => Err(::error::CpuPowerError::SystemError(errno::errno())),
-                _ => Ok(frequency)
-            }
-        }
+        self.read("cpuinfo_cur_freq", self.backend.get_freq_hardware(self.id))
     }
 
     /// Set frequency for the given CPU
     /// You should have root privileges to do that
+    ///
+    /// The governor is switched to userspace and stays so, use
+    /// `set_setspeed` to control that.
     pub fn set_freq(&self, freq: Frequency) -> Result<&Cpu> {
-        unsafe {
-            let result = cpufreq_set_frequency(self.id, freq);
+        self.write("scaling_setspeed", freq, self.backend.set_frequency(self.id, freq)).map(|_| self)
+    }
 
-            match result {
-                0 => Ok(&self),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno()))
-            }
-        }
+    /// Frequency requested through `scaling_setspeed`, which fails with
+    /// `GovernorMismatch` unless the userspace governor is in charge
+    pub fn get_setspeed(&self) -> Result<Frequency> {
+        self.read("scaling_setspeed", setspeed::read_setspeed(&*self.backend, &cpufreq_directory(self.id)))
+    }
+
+    /// Run the cpu at a fixed frequency through `scaling_setspeed`.
+    ///
+    /// Another governor than userspace fails with `GovernorMismatch`
+    /// unless `switch_governor` is set. The result tells the governor in
+    /// charge before, to restore it with `modify_policy_governor`.
+    pub fn set_setspeed(&self, freq: Frequency, switch_governor: bool) -> Result<SetSpeed> {
+        self.write("scaling_setspeed", freq, setspeed::write_setspeed(&*self.backend, &cpufreq_directory(self.id), freq, switch_governor))
     }
 
     /// Determine CPUs transition latency
     /// Returns: transition latency in nanoseconds (10^(-9) s)
     pub fn get_transition_latency(&self) -> Result<u64> {
-        unsafe {
-            let latency = cpufreq_get_transition_latency(self.id as u32);
-            match latency {
-                0 => Err(::error::CpuPowerError::SystemError(errno::errno())),
-                _ => Ok(latency)
-            }
-        }
+        self.read("cpuinfo_transition_latency", self.backend.get_transition_latency(self.id))
     }
 
     /// Modify current policy by changing it's max frequency
     pub fn modify_policy_max(&self, max: Frequency) -> Result<()> {
-        unsafe {
-            let result = cpufreq_modify_policy_max(self.id as u32, max);
-            match result {
-                0 => Ok(()),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno()))
-            }
-        }
+        self.write("scaling_max_freq", max, self.backend.modify_policy_max(self.id, max))
     }
 
     /// Modify current policy by changing it's min frequency
     pub fn modify_policy_min(&self, min: Frequency) -> Result<()> {
-        unsafe {
-            let result = cpufreq_modify_policy_min(self.id as u32, min);
-            match result {
-                0 => Ok(()),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno()))
-            }
+        self.write("scaling_min_freq", min, self.backend.modify_policy_min(self.id, min))
+    }
+
+    /// Change both limits, in the order keeping min below max in between
+    pub fn set_limits(&self, min: Frequency, max: Frequency) -> Result<()> {
+        if max < min {
+            let error = Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+            return self.write("scaling_min_freq, scaling_max_freq", format!("{} {}", min, max), error);
+        }
+
+        if min > self.get_policy()?.max {
+            self.modify_policy_max(max)?;
+            self.modify_policy_min(min)
+        } else {
+            self.modify_policy_min(min)?;
+            self.modify_policy_max(max)
         }
     }
 
     /// Modify current policy by changing it's governor
     pub fn modify_policy_governor(&self, governor: &str) -> Result<()> {
-        let governor = try!(CString::new(governor));
-        unsafe {
-            let result = cpufreq_modify_policy_governor(self.id as u32, governor.as_ptr() as *mut libc::c_char);
-            match result {
-                0 => Ok(()),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno()))
-            }
-        }
+        let result = self.backend.modify_policy_governor(self.id, governor)
+            .map_err(|error| explain_governor(&*self.backend, self.id, governor, error));
+        self.write("scaling_governor", governor, result)
     }
 
     /// Determine hardware CPU frequency limits
@@ -167,15 +296,33 @@ impl Cpu {
     /// These may be limited further by thermal, energy or other
     /// considerations by cpufreq policy notifiers in the kernel.
     pub fn get_hardware_limits(&self) -> Result<(Frequency, Frequency)> {
-        unsafe {
-            let mut min: u64 = 0;
-            let mut max: u64 = 0;
-            let response = cpufreq_get_hardware_limits(self.id as u32, &mut min as *mut u64, &mut max as *mut u64);
-            match response {
-                0 => Ok((min, max)),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno())),
-            }
-        }
+        self.read("cpuinfo_min_freq, cpuinfo_max_freq", self.backend.get_hardware_limits(self.id))
+    }
+
+    /// Whether the cpu is running
+    pub fn is_online(&self) -> Result<bool> {
+        self.read_device("online", self.backend.is_online(self.id))
+    }
+
+    /// Position of the cpu in the machine, only known while it is online
+    pub fn get_topology(&self) -> Result<CpuTopology> {
+        self.read_device("topology", self.backend.get_topology(self.id))
+    }
+
+    /// Relative performance of the cpu, 1024 for the biggest ones. Only
+    /// known on systems with asymmetric cpus (big.LITTLE, hybrid).
+    pub fn get_capacity(&self) -> Result<u64> {
+        self.read_device("cpu_capacity", self.backend.get_capacity(self.id))
+    }
+
+    /// Bring the cpu online or take it offline, which needs root privileges
+    pub fn set_online(&self, online: bool) -> Result<()> {
+        self.write_device("online", online as u8, self.backend.set_online(self.id, online))
+    }
+
+    /// Whether the cpu can be taken offline
+    pub fn is_hotpluggable(&self) -> Result<bool> {
+        self.read_device("online", self.backend.is_hotpluggable(self.id))
     }
 
     /// Get if of the current processor
@@ -185,125 +332,113 @@ impl Cpu {
 
     /// Determine CPUfreq driver used
     pub fn get_driver(&self) -> Result<String> {
-        unsafe {
-            let driver_name_ptr: *mut c_char = cpufreq_get_driver(self.id as u32);
-            // TODO: Too complicated
-
-            if driver_name_ptr.is_null() {
-                return Err(::error::CpuPowerError::SystemError(errno::errno()));
-            }
+        self.read("scaling_driver", self.backend.get_driver(self.id))
+    }
 
-            let driver_name: CString = CStr::from_ptr(driver_name_ptr).to_owned();
-            cpufreq_put_driver(driver_name_ptr);
+    /// Energy performance preference, only with hardware managed P-states
+    /// (`intel_pstate` with HWP, `amd-pstate-epp`)
+    pub fn get_epp(&self) -> Result<Epp> {
+        self.read("energy_performance_preference", epp::read_epp(&*self.backend, &cpufreq_directory(self.id)))
+    }
 
-            match String::from_utf8(driver_name.into_bytes()) {
-                Ok(result) => Ok(result),
-                Err(error) => Err(::error::CpuPowerError::FromUtf8Error(error))
-            }
-        }
+    pub fn get_available_epps(&self) -> Result<Vec<String>> {
+        self.read("energy_performance_available_preferences", epp::read_available_epps(&*self.backend, &cpufreq_directory(self.id)))
     }
 
-    /// # Determine CPUfreq policy used
+    /// Set the energy performance preference, either a name such as
+    /// `"balance_power"` or a raw value (`intel_pstate` only).
     ///
-    /// You can try to change current policy by using set_policy method
-    pub fn get_policy(&self) -> Result<Policy> {
-        unsafe {
-            let policy = cpufreq_get_policy(self.id as u32);
-
-            if policy.is_null() {
-                return Err(::error::CpuPowerError::SystemError(errno::errno()));
-            }
+    /// Names which aren't available fail with `PreferenceUnavailable`.
+    pub fn set_epp<E: Into<Epp>>(&self, epp: E) -> Result<()> {
+        let epp = epp.into();
+        self.write("energy_performance_preference", &epp, epp::write_epp(&*self.backend, &cpufreq_directory(self.id), &epp))
+    }
 
-            let min = (*policy).min;
-            let max = (*policy).max;
+    /// Energy performance bias hint of Intel cpus, from
+    /// `power/energy_perf_bias`
+    pub fn get_energy_perf_bias(&self) -> Result<EnergyPerfBias> {
+        self.read_device("power/energy_perf_bias", energy_perf_bias::read_energy_perf_bias(&*self.backend, self.id))
+    }
 
-            let result = match str::from_utf8(CStr::from_ptr((*policy).governor).to_bytes()) {
-                Ok(governor_name) => Ok(Policy::new(min, max, governor_name)),
-                Err(error) => return Err(::error::CpuPowerError::Utf8Error(error))
-            };
+    /// Set the energy performance bias, e.g. `EnergyPerfBias::BALANCE_POWER`.
+    /// Cpus without it fail with `FeatureMissing`.
+    pub fn set_energy_perf_bias(&self, bias: EnergyPerfBias) -> Result<()> {
+        self.write_device("power/energy_perf_bias", bias, energy_perf_bias::write_energy_perf_bias(&*self.backend, self.id, bias))
+    }
 
-            cpufreq_put_policy(policy);
+    /// CPPC performance levels reported by the `amd-pstate` driver
+    pub fn get_amd_perf(&self) -> Result<AmdPerf> {
+        self.read(amd_pstate::PERF_ATTRIBUTES, amd_pstate::read_amd_perf(&*self.backend, &cpufreq_directory(self.id)))
+    }
 
-            return result;
-        }
+    /// # Determine CPUfreq policy used
+    ///
+    /// You can try to change current policy by using set_policy method
+    pub fn get_policy(&self) -> Result<Policy> {
+        self.read(POLICY_ATTRIBUTES, self.backend.get_policy(self.id))
     }
 
     /// Set new CPUfreq policy to use
     /// This tries to set the passed policy as new policy as close as possible,
     /// but results may differ depending e.g. on governors being available.
     pub fn set_policy(&self, policy: &Policy) -> Result<()> {
-        unsafe {
-            let governor_name = try!(CString::new(policy.governor.clone())); // TODO: Unnecessary here
-            let mut policy = Struct_cpufreq_policy{
-                min: policy.min,
-                max: policy.max,
-                governor: governor_name.as_ptr() as *mut libc::c_char
-            };
-            let result = cpufreq_set_policy(self.id as u32, &mut policy as *mut Struct_cpufreq_policy);
-
-            match result {
-                0 => Ok(()),
-                _ => Err(::error::CpuPowerError::SystemError(errno::errno()))
-            }
-        }
+        let result = self.backend.set_policy(self.id, policy)
+            .map_err(|error| explain_governor(&*self.backend, self.id, &policy.governor, error));
+        self.write(POLICY_ATTRIBUTES, policy, result)
     }
 
     /// determine CPUfreq governors currently available
     ///
     /// may be modified by modprobe'ing or rmmod'ing other governors
     pub fn get_available_governors(&self) -> Result<Vec<String>> {
-        ::adapters::AvailableGovernors::extract(self.get_id())
+        self.read("scaling_available_governors", self.backend.get_available_governors(self.id))
     }
 
     /// Get frequencies available for the given CPU
     pub fn get_available_frequencies(&self) -> Result<Vec<Frequency>> {
-        ::adapters::AvailableFrequencies::extract(self.get_id())
+        self.read("scaling_available_frequencies", self.backend.get_available_frequencies(self.id))
     }
 
-    pub fn get_affected_cpus(&self) -> Result<Vec<Cpu>> {
-        let cpus = try!(::adapters::AffectedCpus::extract(self.get_id()));
-        let mut result = Vec::<Cpu>::new();
-        result.extend(cpus.iter().map(|cpu_id| Cpu::new(*cpu_id)));
-        Ok(result)
+    /// Online cpus sharing the policy of this one, itself included
+    pub fn get_affected_cpus(&self) -> Result<CpuSet> {
+        self.read("affected_cpus", self.backend.get_affected_cpus(self.id))
     }
 
-    pub fn get_related_cpus(&self) -> Result<Vec<Cpu>> {
-        let cpus = try!(::adapters::RelatedCpus::extract(self.get_id()));
-        let mut result = Vec::<Cpu>::new();
-        result.extend(cpus.iter().map(|cpu_id| Cpu::new(*cpu_id)));
-        Ok(result)
+    /// Every cpu sharing the policy of this one, online or not
+    pub fn get_related_cpus(&self) -> Result<CpuSet> {
+        self.read("related_cpus", self.backend.get_related_cpus(self.id))
+    }
+
+    /// Handles for the cpus of the set, sharing the backend of this one
+    pub fn siblings(&self, cpus: &CpuSet) -> Vec<Cpu> {
+        cpus.to_cpus_with(self.backend.clone())
+    }
+
+    /// cpufreq policy the cpu belongs to, shared with its related cpus
+    pub fn get_freq_policy(&self) -> Result<FreqPolicy> {
+        FreqPolicy::of_cpu(self)
+    }
+
+    /// Tunables of the ondemand, conservative or schedutil governor in
+    /// charge of the cpu
+    pub fn get_governor_tunables(&self) -> Result<GovernorTunables> {
+        GovernorTunables::of_cpu(self)
     }
 
     /// Determine stats for the cpufreq subsystem
     pub fn get_stats(&self) -> Result<Vec<Stat>> {
-        ::adapters::Stats::extract(self.get_id())
+        self.read("stats/time_in_state", self.backend.get_stats(self.id))
     }
 
     /// Determine total transition count for this CPU
     pub fn get_transitions(&self) -> Result<u64> {
-        let result: u64;
-
-        errno::errno();  // Cleaning up existing errno
-
-        unsafe {
-            result = cpufreq_get_transitions(self.id as u32) as u64;
-        }
-
-        if result == 0 {
-            let errno = errno::errno();
-
-            if errno.0 != 0 {
-                return Err(::error::CpuPowerError::SystemError(errno))
-            }
-        }
-
-        Ok(result)
+        self.read("stats/total_trans", self.backend.get_transitions(self.id))
     }
 }
 
 
 impl fmt::Display for Cpu {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Cpu{{id: {}, frequency: {}}}", self.get_id(), self.get_freq().map(|freq| freq.to_string()).unwrap_or(String::from("Unknown")))
+        write!(f, "Cpu{{id: {}, frequency: {}}}", self.get_id(), self.get_freq().map(|freq| freq.to_string()).unwrap_or_else(|_| String::from("Unknown")))
     }
 }
@@ -0,0 +1,288 @@
+//! # Sets of cpus
+//!
+//! The kernel describes groups of cpus either as cpu lists (`0-3,8,10-11`,
+//! used by the masks under `/sys/devices/system/cpu`, or just `0 1 2 3`
+//! for `affected_cpus` and `related_cpus`) or as hexadecimal cpu masks
+//! made of comma separated 32-bit words (`00000000,00000d0f`). `CpuSet`
+//! reads and writes both.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{Backend, default_backend};
+use ::cpu::Cpu;
+use ::error::CpuPowerError;
+use ::result::Result;
+use ::types::CpuId;
+
+use std::collections::btree_set;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::{self, FromIterator};
+use std::str::FromStr;
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+/// Cpus of a hexadecimal mask word
+const WORD_BITS: CpuId = 32;
+
+/// Highest id accepted in a cpu list or mask, far above what the kernel
+/// supports. Stops a list such as `0-4294967295` from exhausting the memory.
+const MAX_CPU_ID: CpuId = u16::MAX as CpuId;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CpuSet {
+    ids: BTreeSet<CpuId>
+}
+
+
+impl CpuSet {
+    pub fn new() -> CpuSet {
+        CpuSet::default()
+    }
+
+    /// Parse a cpu list such as `0-3,6,8-9`. Whitespace separated ids, as
+    /// found in `affected_cpus`, are accepted too and an empty list is valid.
+    /// Ids above 65535 are rejected with `InvalidValue`.
+    pub fn from_cpu_list(list: &str) -> Result<CpuSet> {
+        let mut result = CpuSet::new();
+
+        for range in list.split(|c: char| c == ',' || c.is_whitespace()).filter(|range| !range.is_empty()) {
+            let (first, last) = match range.find('-') {
+                Some(dash) => (range[..dash].parse::<CpuId>()?, range[dash + 1..].parse::<CpuId>()?),
+                None => {
+                    let id = range.parse::<CpuId>()?;
+                    (id, id)
+                }
+            };
+
+            if last < first || last > MAX_CPU_ID {
+                return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+            }
+
+            result.ids.extend(first..=last);
+        }
+
+        Ok(result)
+    }
+
+    /// Parse a hexadecimal cpu mask such as `00000000,00000d0f`, the
+    /// lowest bit being cpu 0. Every word is 8 digits long but the leading
+    /// one, which the kernel cuts down to the number of possible cpus
+    /// (`ff` with 8 cpus). Ids above 65535 are rejected with `InvalidValue`.
+    pub fn from_cpu_mask(mask: &str) -> Result<CpuSet> {
+        let invalid = || CpuPowerError::from(errno::Errno(libc::EINVAL));
+        let words: Vec<&str> = mask.trim().split(',').collect();
+
+        for (index, word) in words.iter().enumerate() {
+            let width = word.len();
+
+            if width == 0 || width > 8 || (index > 0 && width != 8) || !word.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+        }
+
+        let mut result = CpuSet::new();
+
+        for (position, digit) in words.concat().chars().rev().enumerate() {
+            let value = digit.to_digit(16).ok_or_else(invalid)?;
+
+            for bit in (0..4).filter(|bit| value & (1 << bit) != 0) {
+                let id = CpuId::try_from(position).ok()
+                    .and_then(|position| position.checked_mul(4))
+                    .and_then(|first| first.checked_add(bit))
+                    .filter(|id| *id <= MAX_CPU_ID)
+                    .ok_or_else(invalid)?;
+                result.ids.insert(id);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Shortest cpu list, e.g. `0-3,8,10-11`
+    pub fn to_cpu_list(&self) -> String {
+        self.to_string()
+    }
+
+    /// Cpu mask in the kernel format, e.g. `00000000,00000d0f`, with at
+    /// least one word
+    pub fn to_cpu_mask(&self) -> String {
+        let words = self.ids.iter().next_back().map(|last| last / WORD_BITS + 1).unwrap_or(1);
+        let mut result: Vec<String> = Vec::new();
+
+        for word in (0..words).rev() {
+            let value = self.ids.range(word * WORD_BITS..(word + 1) * WORD_BITS)
+                .fold(0u32, |value, id| value | (1 << (id % WORD_BITS)));
+            result.push(format!("{:08x}", value));
+        }
+
+        result.join(",")
+    }
+
+    pub fn insert(&mut self, id: CpuId) -> bool {
+        self.ids.insert(id)
+    }
+
+    pub fn remove(&mut self, id: CpuId) -> bool {
+        self.ids.remove(&id)
+    }
+
+    pub fn contains(&self, id: CpuId) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Lowest id, the one naming the policy of related cpus
+    pub fn first(&self) -> Option<CpuId> {
+        self.ids.iter().next().cloned()
+    }
+
+    /// Ids in ascending order
+    pub fn iter(&self) -> iter::Cloned<btree_set::Iter<'_, CpuId>> {
+        self.ids.iter().cloned()
+    }
+
+    pub fn union(&self, other: &CpuSet) -> CpuSet {
+        self.ids.union(&other.ids).cloned().collect()
+    }
+
+    pub fn intersection(&self, other: &CpuSet) -> CpuSet {
+        self.ids.intersection(&other.ids).cloned().collect()
+    }
+
+    /// Cpus of `self` not in `other`
+    pub fn difference(&self, other: &CpuSet) -> CpuSet {
+        self.ids.difference(&other.ids).cloned().collect()
+    }
+
+    pub fn is_subset(&self, other: &CpuSet) -> bool {
+        self.ids.is_subset(&other.ids)
+    }
+
+    /// Handles on your system for the cpus of the set
+    pub fn to_cpus(&self) -> Vec<Cpu> {
+        self.to_cpus_with(default_backend())
+    }
+
+    /// Handles forwarding their calls to the given backend
+    pub fn to_cpus_with(&self, backend: Arc<dyn Backend>) -> Vec<Cpu> {
+        self.iter().map(|id| Cpu::with_backend(backend.clone(), id)).collect()
+    }
+}
+
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ids = self.iter().peekable();
+        let mut separator = "";
+
+        while let Some(first) = ids.next() {
+            let mut last = first;
+
+            while ids.peek() == Some(&(last + 1)) {
+                last = ids.next().unwrap();
+            }
+
+            if first == last {
+                write!(f, "{}{}", separator, first)?;
+            } else {
+                write!(f, "{}{}-{}", separator, first, last)?;
+            }
+
+            separator = ",";
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Parse a cpu list, see `CpuSet::from_cpu_list`
+impl FromStr for CpuSet {
+    type Err = CpuPowerError;
+
+    fn from_str(list: &str) -> Result<CpuSet> {
+        CpuSet::from_cpu_list(list)
+    }
+}
+
+
+impl FromIterator<CpuId> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = CpuId>>(ids: I) -> CpuSet {
+        CpuSet { ids: ids.into_iter().collect() }
+    }
+}
+
+
+impl<'a> FromIterator<&'a Cpu> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = &'a Cpu>>(cpus: I) -> CpuSet {
+        cpus.into_iter().map(Cpu::get_id).collect()
+    }
+}
+
+
+impl FromIterator<Cpu> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = Cpu>>(cpus: I) -> CpuSet {
+        cpus.into_iter().map(|cpu| cpu.get_id()).collect()
+    }
+}
+
+
+impl Extend<CpuId> for CpuSet {
+    fn extend<I: IntoIterator<Item = CpuId>>(&mut self, ids: I) {
+        self.ids.extend(ids)
+    }
+}
+
+
+impl<'a> From<&'a [CpuId]> for CpuSet {
+    fn from(ids: &'a [CpuId]) -> CpuSet {
+        ids.iter().cloned().collect()
+    }
+}
+
+
+impl<'a> From<&'a [Cpu]> for CpuSet {
+    fn from(cpus: &'a [Cpu]) -> CpuSet {
+        cpus.iter().collect()
+    }
+}
+
+
+impl From<Vec<Cpu>> for CpuSet {
+    fn from(cpus: Vec<Cpu>) -> CpuSet {
+        cpus.into_iter().collect()
+    }
+}
+
+
+impl IntoIterator for CpuSet {
+    type Item = CpuId;
+    type IntoIter = btree_set::IntoIter<CpuId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ids.into_iter()
+    }
+}
+
+
+impl<'a> IntoIterator for &'a CpuSet {
+    type Item = CpuId;
+    type IntoIter = iter::Cloned<btree_set::Iter<'a, CpuId>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,128 @@
+//! # Energy performance bias
+//!
+//! Intel cpus take a hint between 0 (performance) and 15 (power saving)
+//! in the `IA32_ENERGY_PERF_BIAS` register, exposed as
+//! `/sys/devices/system/cpu/cpuN/power/energy_perf_bias`. Older machines
+//! without HWP rely on it, newer ones use the energy performance
+//! preference instead.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::Backend;
+use ::error::CpuPowerError;
+use ::result::Result;
+use ::types::CpuId;
+
+use std::fmt;
+use std::str::FromStr;
+use std::string::String;
+
+
+/// Values with a name, as known to the kernel
+const NAMES: [(&str, u8); 5] = [
+    ("performance", 0),
+    ("balance-performance", 4),
+    ("normal", 6),
+    ("balance-power", 8),
+    ("power", 15)
+];
+
+const MAX: u8 = 15;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EnergyPerfBias {
+    value: u8
+}
+
+
+impl EnergyPerfBias {
+    pub const PERFORMANCE: EnergyPerfBias = EnergyPerfBias { value: 0 };
+    pub const BALANCE_PERFORMANCE: EnergyPerfBias = EnergyPerfBias { value: 4 };
+    pub const NORMAL: EnergyPerfBias = EnergyPerfBias { value: 6 };
+    pub const BALANCE_POWER: EnergyPerfBias = EnergyPerfBias { value: 8 };
+    pub const POWER: EnergyPerfBias = EnergyPerfBias { value: 15 };
+
+    /// Bias from a raw value, up to 15
+    pub fn new(value: u8) -> Result<EnergyPerfBias> {
+        if value > MAX {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        Ok(EnergyPerfBias { value })
+    }
+
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+
+    /// Name of the level, `None` for values in between
+    pub fn name(&self) -> Option<&'static str> {
+        NAMES.iter().find(|named| named.1 == self.value).map(|named| named.0)
+    }
+}
+
+
+/// The name of the level if it has one, the value otherwise
+impl fmt::Display for EnergyPerfBias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.value)
+        }
+    }
+}
+
+
+/// Parse a name such as `balance-power` or a value from 0 to 15
+impl FromStr for EnergyPerfBias {
+    type Err = CpuPowerError;
+
+    fn from_str(value: &str) -> Result<EnergyPerfBias> {
+        let value = value.trim();
+
+        match NAMES.iter().find(|named| named.0 == value) {
+            Some(named) => Ok(EnergyPerfBias { value: named.1 }),
+            None => EnergyPerfBias::new(value.parse()?)
+        }
+    }
+}
+
+
+/// Bias of the cpu, `FeatureMissing` if it has none
+pub fn read_energy_perf_bias<B: Backend + ?Sized>(backend: &B, id: CpuId) -> Result<EnergyPerfBias> {
+    backend.read_attribute(&attribute(id))
+        .map_err(|error| explain_missing_bias(backend, id, error))?
+        .parse()
+}
+
+
+pub fn write_energy_perf_bias<B: Backend + ?Sized>(backend: &B, id: CpuId, bias: EnergyPerfBias) -> Result<()> {
+    backend.write_attribute(&attribute(id), &bias.value().to_string())
+        .map_err(|error| explain_missing_bias(backend, id, error))
+}
+
+
+fn attribute(id: CpuId) -> String {
+    format!("cpu{}/power/energy_perf_bias", id)
+}
+
+
+/// Turn a missing attribute into `CpuNotFound`, `CpuOffline` or, for an
+/// online cpu, `FeatureMissing`. The attribute isn't part of cpufreq, so
+/// a missing driver isn't a reason.
+fn explain_missing_bias<B: Backend + ?Sized>(backend: &B, id: CpuId, error: CpuPowerError) -> CpuPowerError {
+    match error {
+        CpuPowerError::NotSupported{errno} if errno.0 == libc::ENOENT => {
+            if !backend.cpu_exists(id) {
+                CpuPowerError::CpuNotFound { id }
+            } else if backend.is_online(id).unwrap_or(false) {
+                CpuPowerError::FeatureMissing { id, feature: "energy_perf_bias", errno }
+            } else {
+                CpuPowerError::CpuOffline { id, errno }
+            }
+        },
+        error => error
+    }
+}
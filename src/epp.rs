@@ -0,0 +1,98 @@
+//! # Energy performance preference
+//!
+//! With hardware managed P-states (`intel_pstate` with HWP, `amd-pstate-epp`)
+//! the hardware picks the frequency itself, steered by
+//! `energy_performance_preference`: one of the names listed in
+//! `energy_performance_available_preferences` or, with `intel_pstate` only,
+//! a raw value from 0 (performance) to 255 (power saving).
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend};
+use ::error::CpuPowerError;
+use ::result::Result;
+
+use std::fmt;
+use std::str::FromStr;
+use std::string::String;
+use std::vec::Vec;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Epp {
+    /// A preference of `energy_performance_available_preferences`, e.g.
+    /// `balance_power`
+    Named(String),
+    /// Raw value, 0 favoring performance and 255 energy saving
+    Value(u8)
+}
+
+
+impl fmt::Display for Epp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Epp::Named(ref name) => write!(f, "{}", name),
+            Epp::Value(value) => write!(f, "{}", value)
+        }
+    }
+}
+
+
+/// Numbers are raw values, anything else a name
+impl FromStr for Epp {
+    type Err = CpuPowerError;
+
+    fn from_str(value: &str) -> Result<Epp> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+        } else if value.chars().all(|c| c.is_ascii_digit()) {
+            Ok(Epp::Value(value.parse()?))
+        } else {
+            Ok(Epp::Named(String::from(value)))
+        }
+    }
+}
+
+
+impl<'a> From<&'a str> for Epp {
+    fn from(name: &'a str) -> Epp {
+        Epp::Named(String::from(name))
+    }
+}
+
+
+impl From<u8> for Epp {
+    fn from(value: u8) -> Epp {
+        Epp::Value(value)
+    }
+}
+
+
+/// Preference from a cpufreq directory (`cpuN/cpufreq` or `cpufreq/policyN`)
+pub fn read_epp<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<Epp> {
+    backend.read_attribute(&format!("{}/energy_performance_preference", directory))?.parse()
+}
+
+
+pub fn read_available_epps<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<Vec<String>> {
+    backend::read_words(backend, &format!("{}/energy_performance_available_preferences", directory))
+}
+
+
+/// Write the preference, names being checked against the available ones
+/// first so a typo gets a clear error
+pub fn write_epp<B: Backend + ?Sized>(backend: &B, directory: &str, epp: &Epp) -> Result<()> {
+    if let Epp::Named(ref name) = *epp {
+        if !read_available_epps(backend, directory)?.iter().any(|available| available == name) {
+            return Err(CpuPowerError::PreferenceUnavailable {
+                preference: name.clone(),
+                errno: errno::Errno(libc::EINVAL)
+            });
+        }
+    }
+
+    backend.write_attribute(&format!("{}/energy_performance_preference", directory), &epp.to_string())
+}
@@ -1,19 +1,143 @@
 extern crate errno;
+extern crate libc;
 
 use std::ops::Deref;
 use std::ffi;
 use std::error;
 use std::fmt;
+use std::io;
+use std::num;
 use std::str;
 use std::string;
 
 
+/// What a `Cpu` operation was doing with the attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write
+}
+
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Read => write!(f, "read"),
+            Operation::Write => write!(f, "write")
+        }
+    }
+}
+
+
+/// Where a `Cpu` (or `FreqPolicy`, or driver wide) operation failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// `None` for the global knobs such as `intel_pstate/no_turbo`
+    pub cpu: Option<::types::CpuId>,
+    /// N of `cpufreq/policyN` for errors returned by `FreqPolicy`, `cpu`
+    /// is then its first online cpu
+    pub policy: Option<u32>,
+    pub operation: Operation,
+    /// Attribute under `cpuN/cpufreq` (several of them, comma separated,
+    /// for operations touching more than one), or under `cpuN` for the
+    /// attributes outside of cpufreq such as `topology` or `online`. The
+    /// path relative to `/sys/devices/system/cpu` for the global knobs.
+    pub attribute: String,
+    /// Value being written
+    pub value: Option<String>
+}
+
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let owner = match (self.policy, self.cpu) {
+            (Some(policy), _) => format!(" of policy {}", policy),
+            (None, Some(cpu)) => format!(" of cpu {}", cpu),
+            (None, None) => String::new()
+        };
+
+        match self.value {
+            Some(ref value) => write!(f, "Can't write {:?} to {}{}", value, self.attribute, owner),
+            None => write!(f, "Can't {} {}{}", self.operation, self.attribute, owner)
+        }
+    }
+}
+
+
+/// Errors of the library.
+///
+/// Failures reported by the kernel (or libcpupower) are mapped to the
+/// dedicated variants below whenever possible, `SystemError` is left for
+/// the errno values without one. The original errno is kept in every case
+/// and is returned by `errno()`.
+///
+/// Errors returned by `Cpu` and `FreqPolicy` are wrapped into
+/// `OperationFailed` telling what was attempted, use `kind()` to match on
+/// the underlying error.
 #[derive(Debug)]
 pub enum CpuPowerError {
     Unknown,
+    OperationFailed {
+        context: ErrorContext,
+        source: Box<CpuPowerError>
+    },
     CpuNotFound {
         id: ::types::CpuId
     },
+    /// The cpu exists but is offline, so it has no cpufreq attributes
+    CpuOffline {
+        id: ::types::CpuId,
+        errno: errno::Errno
+    },
+    /// No cpufreq driver handles the cpu
+    DriverMissing {
+        id: ::types::CpuId,
+        errno: errno::Errno
+    },
+    /// The cpu has no `online` attribute, it can't be taken offline
+    NotHotpluggable {
+        id: ::types::CpuId
+    },
+    /// The kernel refused to bring the cpu online or offline, e.g. it is
+    /// the last online cpu
+    HotplugRefused {
+        id: ::types::CpuId,
+        online: bool,
+        errno: errno::Errno
+    },
+    /// The cpu lacks an optional feature, e.g. `energy_perf_bias`
+    FeatureMissing {
+        id: ::types::CpuId,
+        feature: &'static str,
+        errno: errno::Errno
+    },
+    /// EACCES, EPERM or EROFS: usually root privileges are needed
+    PermissionDenied {
+        errno: errno::Errno
+    },
+    /// The attribute doesn't exist for this cpu, driver or kernel
+    NotSupported {
+        errno: errno::Errno
+    },
+    /// The value was rejected
+    InvalidValue {
+        errno: errno::Errno
+    },
+    /// The governor isn't among the available ones (or can't be a governor name at all)
+    GovernorUnavailable {
+        governor: String,
+        errno: errno::Errno
+    },
+    /// The operation needs another governor than the one in charge
+    GovernorMismatch {
+        governor: String,
+        required: &'static str
+    },
+    /// The energy performance preference isn't among the available ones
+    PreferenceUnavailable {
+        preference: String,
+        errno: errno::Errno
+    },
     SystemError(errno::Errno),
     FrequencyNotSet{
         id: ::types::CpuId,
@@ -23,15 +147,71 @@ pub enum CpuPowerError {
     },
     CantGetPolicy{
         id: ::types::CpuId,
-        parent: Box<error::Error>
+        parent: Box<dyn error::Error + Send + Sync>
     },
     CantSetPolicy{
         id: ::types::CpuId,
-        parent: Box<error::Error>
+        parent: Box<dyn error::Error + Send + Sync>
     },
     Utf8Error(str::Utf8Error),
     FromUtf8Error(string::FromUtf8Error),
-    NulError(ffi::NulError)
+    NulError(ffi::NulError),
+    ParseIntError(num::ParseIntError),
+    /// I/O failure without an errno or a dedicated variant
+    IoError(io::Error),
+    MalformedFixture{
+        offset: usize,
+        reason: &'static str
+    }
+}
+
+
+impl CpuPowerError {
+    /// Variant matching the errno, `SystemError` if there is no dedicated one
+    pub fn from_errno(errno: errno::Errno) -> CpuPowerError {
+        match errno.0 {
+            libc::EACCES | libc::EPERM | libc::EROFS => CpuPowerError::PermissionDenied { errno },
+            libc::ENOENT | libc::ENOSYS | libc::EOPNOTSUPP | libc::ENOTTY => CpuPowerError::NotSupported { errno },
+            libc::EINVAL | libc::ERANGE => CpuPowerError::InvalidValue { errno },
+            _ => CpuPowerError::SystemError(errno)
+        }
+    }
+
+    /// The error without the context added by `Cpu` or `FreqPolicy`
+    pub fn kind(&self) -> &CpuPowerError {
+        match *self {
+            CpuPowerError::OperationFailed{ref source, ..} => source.kind(),
+            _ => self
+        }
+    }
+
+    /// What was being done when the error happened, for errors returned by
+    /// `Cpu` and `FreqPolicy`
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            CpuPowerError::OperationFailed{ref context, ..} => Some(context),
+            _ => None
+        }
+    }
+
+    /// The errno the error originates from, if any
+    pub fn errno(&self) -> Option<errno::Errno> {
+        match *self {
+            CpuPowerError::OperationFailed{ref source, ..} => source.errno(),
+            CpuPowerError::CpuOffline{errno, ..} |
+            CpuPowerError::DriverMissing{errno, ..} |
+            CpuPowerError::HotplugRefused{errno, ..} |
+            CpuPowerError::FeatureMissing{errno, ..} |
+            CpuPowerError::PermissionDenied{errno} |
+            CpuPowerError::NotSupported{errno} |
+            CpuPowerError::InvalidValue{errno} |
+            CpuPowerError::GovernorUnavailable{errno, ..} |
+            CpuPowerError::PreferenceUnavailable{errno, ..} |
+            CpuPowerError::SystemError(errno) |
+            CpuPowerError::FrequencyNotSet{errno, ..} => Some(errno),
+            _ => None
+        }
+    }
 }
 
 
@@ -40,6 +220,38 @@ impl fmt::Display for CpuPowerError {
         match *self {
             CpuPowerError::Unknown => write!(f, "Unknown error"),
             CpuPowerError::CpuNotFound{id} => write!(f, "Cpu {} not found", id),
+            CpuPowerError::OperationFailed{
+                ref context,
+                ref source
+            } => write!(f, "{}: {}", context, source),
+            CpuPowerError::CpuOffline{id, errno} => write!(f, "Cpu {} is offline, bring it online first ({})", id, errno),
+            CpuPowerError::DriverMissing{id, errno} => write!(f, "No cpufreq driver handles cpu {}, is the driver module loaded? ({})", id, errno),
+            CpuPowerError::NotHotpluggable{id} => write!(f, "Cpu {} can't be taken offline", id),
+            CpuPowerError::HotplugRefused{
+                id,
+                online,
+                errno
+            } => write!(f, "The kernel refused to bring cpu {} {} ({})", id, if online { "online" } else { "offline" }, errno),
+            CpuPowerError::FeatureMissing{
+                id,
+                feature,
+                errno
+            } => write!(f, "Cpu {} doesn't support {} ({})", id, feature, errno),
+            CpuPowerError::PermissionDenied{errno} => write!(f, "{}, root privileges are usually required", errno),
+            CpuPowerError::NotSupported{errno} => write!(f, "{}, not supported by the kernel or the cpufreq driver", errno),
+            CpuPowerError::InvalidValue{errno} => write!(f, "{}, the value was rejected", errno),
+            CpuPowerError::GovernorUnavailable{
+                ref governor,
+                errno
+            } => write!(f, "Governor {:?} is not available, see scaling_available_governors ({})", governor, errno),
+            CpuPowerError::GovernorMismatch{
+                ref governor,
+                required
+            } => write!(f, "The {:?} governor is in charge, this needs {:?}", governor, required),
+            CpuPowerError::PreferenceUnavailable{
+                ref preference,
+                errno
+            } => write!(f, "Energy performance preference {:?} is not available, see energy_performance_available_preferences ({})", preference, errno),
             CpuPowerError::FrequencyNotSet{
                 id,
                 requested,
@@ -58,35 +270,38 @@ impl fmt::Display for CpuPowerError {
             CpuPowerError::Utf8Error(ref err) => write!(f, "UTF-8 conversion error: {}", err),
             CpuPowerError::FromUtf8Error(ref err) => write!(f, "UTF-8 conversion error: {}", err),
             CpuPowerError::NulError(ref err) => write!(f, "Null pointer passed: {}", err),
+            CpuPowerError::ParseIntError(ref err) => write!(f, "Can't parse sysfs value: {}", err),
+            CpuPowerError::IoError(ref err) => write!(f, "I/O error: {}", err),
+            CpuPowerError::MalformedFixture{
+                offset,
+                reason
+            } => write!(f, "Malformed fixture at byte {}: {}", offset, reason),
         }
     }
 }
 
 impl error::Error for CpuPowerError {
-    fn description(&self) -> &str {
-        match *self {
-            CpuPowerError::Unknown => "Unknown error occured",
-            CpuPowerError::CpuNotFound{id: _} => "Cpu with that id not found",
-            CpuPowerError::SystemError(_) => "System error represented by errno value",
-            CpuPowerError::FrequencyNotSet{id: _, requested: _, actual: _, errno: _} => "Frequency wasn't set",
-            CpuPowerError::CantGetPolicy{id: _, parent: _} => "Can't get policy",
-            CpuPowerError::CantSetPolicy{id: _, parent: _} => "Can't set policy",
-            CpuPowerError::Utf8Error(ref err) => error::Error::description(err),
-            CpuPowerError::FromUtf8Error(ref err) => error::Error::description(err),
-            CpuPowerError::NulError(ref err) => error::Error::description(err)
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
+            CpuPowerError::OperationFailed{ref source, ..} => Some(source.deref()),
             CpuPowerError::Utf8Error(ref err) => Some(err),
             CpuPowerError::FromUtf8Error(ref err) => Some(err),
-            CpuPowerError::CantGetPolicy{id: _, ref parent} => Some(parent.deref()),
+            CpuPowerError::NulError(ref err) => Some(err),
+            CpuPowerError::ParseIntError(ref err) => Some(err),
+            CpuPowerError::IoError(ref err) => Some(err),
+            CpuPowerError::CantGetPolicy{ref parent, ..} => Some(parent.deref()),
+            CpuPowerError::CantSetPolicy{ref parent, ..} => Some(parent.deref()),
             _ => None
         }
     }
 }
 
+impl From<errno::Errno> for CpuPowerError {
+    fn from(errno: errno::Errno) -> CpuPowerError {
+        CpuPowerError::from_errno(errno)
+    }
+}
+
 impl From<str::Utf8Error> for CpuPowerError {
     fn from(error: str::Utf8Error) -> CpuPowerError {
         CpuPowerError::Utf8Error(error)
@@ -104,3 +319,24 @@ impl From<ffi::NulError> for CpuPowerError {
         CpuPowerError::NulError(source)
     }
 }
+
+impl From<num::ParseIntError> for CpuPowerError {
+    fn from(error: num::ParseIntError) -> CpuPowerError {
+        CpuPowerError::ParseIntError(error)
+    }
+}
+
+/// Errors without an errno get the one matching their kind, if any
+impl From<io::Error> for CpuPowerError {
+    fn from(error: io::Error) -> CpuPowerError {
+        let code = match (error.raw_os_error(), error.kind()) {
+            (Some(code), _) => code,
+            (None, io::ErrorKind::NotFound) => libc::ENOENT,
+            (None, io::ErrorKind::PermissionDenied) => libc::EACCES,
+            (None, io::ErrorKind::InvalidData) | (None, io::ErrorKind::InvalidInput) => libc::EINVAL,
+            (None, _) => return CpuPowerError::IoError(error)
+        };
+
+        CpuPowerError::from_errno(errno::Errno(code))
+    }
+}
@@ -0,0 +1,349 @@
+//! # Portable snapshot of the cpufreq sysfs attributes
+//!
+//! `Fixture::capture` records the attributes the library reads from a
+//! running system (or from any other backend) so a bug seen on a machine
+//! at hand can be reproduced anywhere else. A fixture is saved either as a
+//! directory with the same layout as `/sys/devices/system/cpu` or as a
+//! single JSON object mapping attribute paths to their content, and can be
+//! given to `Cpu::with_backend` as a read-only backend.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend};
+use ::error::CpuPowerError;
+use ::result::Result;
+use ::sysfs::{self, SysfsRoot};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+
+/// Files directly under `/sys/devices/system/cpu` worth keeping
+const ROOT_ATTRIBUTES: [&str; 5] = ["possible", "present", "online", "offline", "kernel_max"];
+
+/// Directories directly under `/sys/devices/system/cpu` recorded as a whole
+const ROOT_DIRECTORIES: [&str; 3] = ["cpufreq", "intel_pstate", "amd_pstate"];
+
+/// Directories nested deeper than that are not followed while walking
+const MAX_DEPTH: usize = 4;
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fixture {
+    attributes: BTreeMap<String, String>
+}
+
+
+impl Fixture {
+    /// Empty fixture, fill it with `with_attribute`
+    pub fn new() -> Fixture {
+        Fixture::default()
+    }
+
+    /// Record the cpufreq attributes of the live system
+    pub fn capture_system() -> Result<Fixture> {
+        Fixture::capture(&SysfsRoot::default())
+    }
+
+    /// Record the cpu masks, `cpuN/online`, `cpuN/cpu_capacity`,
+    /// `cpuN/power/energy_perf_bias` and everything under `cpuN/cpufreq`,
+    /// `cpuN/topology` and the global `cpufreq`, `intel_pstate` and
+    /// `amd_pstate` directories.
+    ///
+    /// Attributes which can't be read (e.g. write-only ones) are skipped.
+    pub fn capture<B: Backend + ?Sized>(source: &B) -> Result<Fixture> {
+        let mut fixture = Fixture::new();
+
+        for name in &ROOT_ATTRIBUTES {
+            fixture.record(source, name);
+        }
+
+        for entry in source.list_directory("")? {
+            if ROOT_DIRECTORIES.contains(&entry.as_str()) {
+                fixture.walk(source, &entry, 0);
+            } else if backend::parse_cpu_directory(&entry).is_some() {
+                fixture.record(source, &format!("{}/online", entry));
+                fixture.record(source, &format!("{}/cpu_capacity", entry));
+                fixture.record(source, &format!("{}/power/energy_perf_bias", entry));
+                fixture.walk(source, &format!("{}/cpufreq", entry), 0);
+                fixture.walk(source, &format!("{}/topology", entry), 0);
+            }
+        }
+
+        Ok(fixture)
+    }
+
+    /// Load a fixture saved with `save_dir` (or any copy of a sysfs tree)
+    pub fn load_dir<P: AsRef<Path>>(path: P) -> Result<Fixture> {
+        let mut fixture = Fixture::new();
+        fixture.walk(&SysfsRoot::new(path.as_ref()), "", 0);
+        Ok(fixture)
+    }
+
+    /// Write every attribute into its own file under `path`
+    pub fn save_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        for (attribute, value) in &self.attributes {
+            let file = path.as_ref().join(attribute);
+
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(file, format!("{}\n", value))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_json<P: AsRef<Path>>(path: P) -> Result<Fixture> {
+        Fixture::from_json(&sysfs::read_file(path.as_ref())?)
+    }
+
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    /// Parse a JSON object whose keys are attribute paths and values their content
+    pub fn from_json(json: &str) -> Result<Fixture> {
+        let mut parser = JsonParser { input: json.as_bytes(), offset: 0 };
+        let attributes = parser.parse_object()?;
+
+        parser.skip_whitespace();
+        if parser.offset != json.len() {
+            return Err(parser.error("trailing characters after the object"));
+        }
+
+        Ok(Fixture { attributes })
+    }
+
+    /// JSON object with one attribute per line, sorted by path
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{");
+
+        for (index, (attribute, value)) in self.attributes.iter().enumerate() {
+            json.push_str(if index == 0 { "\n  " } else { ",\n  " });
+            push_json_string(&mut json, attribute);
+            json.push_str(": ");
+            push_json_string(&mut json, value);
+        }
+
+        json.push_str("\n}\n");
+        json
+    }
+
+    /// Add an attribute, `path` is relative to `/sys/devices/system/cpu`
+    pub fn with_attribute(mut self, path: &str, value: &str) -> Fixture {
+        self.attributes.insert(String::from(path.trim_matches('/')), String::from(value.trim_end()));
+        self
+    }
+
+    /// Recorded attributes by their path relative to `/sys/devices/system/cpu`
+    pub fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+
+    fn record<B: Backend + ?Sized>(&mut self, source: &B, path: &str) {
+        if let Ok(value) = source.read_attribute(path) {
+            self.attributes.insert(String::from(path), value);
+        }
+    }
+
+    /// Record everything below `path`, whatever can't be listed is taken for a file
+    fn walk<B: Backend + ?Sized>(&mut self, source: &B, path: &str, depth: usize) {
+        let entries = match source.list_directory(path) {
+            Ok(entries) => entries,
+            Err(_) => return self.record(source, path)
+        };
+
+        if depth >= MAX_DEPTH {
+            return;
+        }
+
+        for entry in entries {
+            let child = if path.is_empty() { entry } else { format!("{}/{}", path, entry) };
+            self.walk(source, &child, depth + 1);
+        }
+    }
+}
+
+
+/// Read-only: every write fails with EROFS
+impl Backend for Fixture {
+    fn read_attribute(&self, path: &str) -> Result<String> {
+        self.attributes.get(path.trim_matches('/')).cloned()
+            .ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+
+    fn write_attribute(&self, _path: &str, _value: &str) -> Result<()> {
+        Err(CpuPowerError::from(errno::Errno(libc::EROFS)))
+    }
+
+    fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+        backend::list_children(self.attributes.keys(), path)
+    }
+}
+
+
+fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+
+    json.push('"');
+}
+
+
+/// Just enough of JSON to read a flat object of strings back
+struct JsonParser<'a> {
+    input: &'a [u8],
+    offset: usize
+}
+
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, reason: &'static str) -> CpuPowerError {
+        CpuPowerError::MalformedFixture { offset: self.offset, reason }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.offset < self.input.len() && (self.input[self.offset] as char).is_ascii_whitespace() {
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8, reason: &'static str) -> Result<()> {
+        self.skip_whitespace();
+
+        if self.input.get(self.offset) == Some(&expected) {
+            self.offset += 1;
+            Ok(())
+        } else {
+            Err(self.error(reason))
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<BTreeMap<String, String>> {
+        let mut result = BTreeMap::new();
+        self.expect(b'{', "expected '{'")?;
+
+        self.skip_whitespace();
+        if self.input.get(self.offset) == Some(&b'}') {
+            self.offset += 1;
+            return Ok(result);
+        }
+
+        loop {
+            let key = self.parse_string()?;
+            self.expect(b':', "expected ':'")?;
+            let value = self.parse_string()?;
+            result.insert(key, value);
+
+            self.skip_whitespace();
+            match self.input.get(self.offset) {
+                Some(&b',') => self.offset += 1,
+                Some(&b'}') => {
+                    self.offset += 1;
+                    return Ok(result);
+                },
+                _ => return Err(self.error("expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect(b'"', "expected a string")?;
+        let mut result = Vec::new();
+
+        loop {
+            let byte = match self.input.get(self.offset) {
+                Some(byte) => *byte,
+                None => return Err(self.error("unterminated string"))
+            };
+            self.offset += 1;
+
+            match byte {
+                b'"' => return Ok(String::from_utf8(result)?),
+                b'\\' => {
+                    let c = self.parse_escape()?;
+                    let mut buffer = [0; 4];
+                    result.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                },
+                byte => result.push(byte)
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char> {
+        let escape = match self.input.get(self.offset) {
+            Some(escape) => *escape,
+            None => return Err(self.error("unterminated string"))
+        };
+        self.offset += 1;
+
+        let c = match escape {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let high = self.parse_hex()?;
+
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if self.input[self.offset..].starts_with(b"\\u") {
+                        self.offset += 2;
+                    } else {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+
+                    let low = self.parse_hex()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+
+                return ::std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"));
+            },
+            _ => return Err(self.error("invalid escape"))
+        };
+
+        Ok(c)
+    }
+
+    fn parse_hex(&mut self) -> Result<u32> {
+        let digits = self.input.get(self.offset..self.offset + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| ::std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+
+        match digits {
+            Some(value) => {
+                self.offset += 4;
+                Ok(value)
+            },
+            None => Err(self.error("invalid unicode escape"))
+        }
+    }
+}
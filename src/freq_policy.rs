@@ -0,0 +1,251 @@
+//! # cpufreq policies
+//!
+//! The kernel scales the frequency per policy, shared by one or more cpus.
+//! Each policy is a `cpufreq/policyN` directory (N being the first cpu of
+//! the policy) and `cpuN/cpufreq` of every member cpu points to it.
+//! `FreqPolicy` works on that directory directly, so a setting is applied
+//! once for all the cpus sharing it.
+
+use ::amd_pstate::{self, AmdPerf};
+use ::backend::{self, Backend, POLICY_ATTRIBUTES, default_backend, explain_governor, explain_missing};
+use ::cpu::Cpu;
+use ::cpu_set::CpuSet;
+use ::epp::{self, Epp};
+use ::error::{CpuPowerError, ErrorContext, Operation};
+use ::policy::Policy;
+use ::result::Result;
+use ::setspeed::{self, SetSpeed};
+use ::stat::Stat;
+use ::sysfs::SysfsRoot;
+use ::types::{CpuId, Frequency};
+
+use std::fmt;
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+#[derive(Debug, Clone)]
+pub struct FreqPolicy {
+    /// N of `cpufreq/policyN`
+    id: u32,
+    backend: Arc<dyn Backend>
+}
+
+
+impl FreqPolicy {
+    /// All the policies of your system, ordered by number
+    pub fn get_all() -> Result<Vec<FreqPolicy>> {
+        FreqPolicy::get_all_with(default_backend())
+    }
+
+    /// All the policies found in the given sysfs tree
+    pub fn get_all_in(root: &SysfsRoot) -> Result<Vec<FreqPolicy>> {
+        FreqPolicy::get_all_with(Arc::new(root.clone()))
+    }
+
+    /// All the policies known to the given backend
+    pub fn get_all_with(backend: Arc<dyn Backend>) -> Result<Vec<FreqPolicy>> {
+        let mut ids: Vec<u32> = backend.list_directory("cpufreq")?.iter()
+            .filter(|entry| entry.starts_with("policy"))
+            .filter_map(|entry| entry[6..].parse().ok())
+            .collect();
+        ids.sort();
+
+        Ok(ids.into_iter().map(|id| FreqPolicy::with_backend(backend.clone(), id)).collect())
+    }
+
+    pub fn new(id: u32) -> FreqPolicy {
+        FreqPolicy::with_backend(default_backend(), id)
+    }
+
+    /// Policy of the given sysfs tree
+    pub fn new_in(root: &SysfsRoot, id: u32) -> FreqPolicy {
+        FreqPolicy::with_backend(Arc::new(root.clone()), id)
+    }
+
+    pub fn with_backend(backend: Arc<dyn Backend>, id: u32) -> FreqPolicy {
+        FreqPolicy {
+            id,
+            backend
+        }
+    }
+
+    /// Policy the cpu belongs to, looked up on the backend of the cpu
+    pub fn of_cpu(cpu: &Cpu) -> Result<FreqPolicy> {
+        for policy in FreqPolicy::get_all_with(cpu.get_backend().clone())? {
+            if policy.get_related_cpus()?.contains(cpu.get_id()) {
+                return Ok(policy);
+            }
+        }
+
+        Err(CpuPowerError::CpuNotFound { id: cpu.get_id() })
+    }
+
+    /// N of `cpufreq/policyN`
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_backend(&self) -> &Arc<dyn Backend> {
+        &self.backend
+    }
+
+    /// Every cpu of the policy, online or not
+    pub fn get_related_cpus(&self) -> Result<CpuSet> {
+        self.read("related_cpus", backend::read_cpu_set(&*self.backend, &self.attribute("related_cpus")))
+    }
+
+    /// Online cpus of the policy, empty when the policy is inactive
+    pub fn get_affected_cpus(&self) -> Result<CpuSet> {
+        self.read("affected_cpus", backend::read_cpu_set(&*self.backend, &self.attribute("affected_cpus")))
+    }
+
+    /// Handles for the cpus of the set, sharing the backend of the policy
+    pub fn cpus(&self, cpus: &CpuSet) -> Vec<Cpu> {
+        cpus.to_cpus_with(self.backend.clone())
+    }
+
+    /// Current frequency according to the kernel, in kHz
+    pub fn get_freq(&self) -> Result<Frequency> {
+        self.read("scaling_cur_freq", backend::read_value(&*self.backend, &self.attribute("scaling_cur_freq")))
+    }
+
+    /// Frequency requested with the userspace governor, see `Cpu::get_setspeed`
+    pub fn get_setspeed(&self) -> Result<Frequency> {
+        self.read("scaling_setspeed", setspeed::read_setspeed(&*self.backend, &self.directory()))
+    }
+
+    /// Run the cpus of the policy at a fixed frequency, see `Cpu::set_setspeed`
+    pub fn set_setspeed(&self, freq: Frequency, switch_governor: bool) -> Result<SetSpeed> {
+        self.write("scaling_setspeed", freq, setspeed::write_setspeed(&*self.backend, &self.directory(), freq, switch_governor))
+    }
+
+    pub fn get_hardware_limits(&self) -> Result<(Frequency, Frequency)> {
+        let min = self.read("cpuinfo_min_freq", backend::read_value(&*self.backend, &self.attribute("cpuinfo_min_freq")))?;
+        let max = self.read("cpuinfo_max_freq", backend::read_value(&*self.backend, &self.attribute("cpuinfo_max_freq")))?;
+        Ok((min, max))
+    }
+
+    /// Transition latency in nanoseconds
+    pub fn get_transition_latency(&self) -> Result<u64> {
+        self.read("cpuinfo_transition_latency", backend::read_value(&*self.backend, &self.attribute("cpuinfo_transition_latency")))
+    }
+
+    pub fn get_driver(&self) -> Result<String> {
+        self.read("scaling_driver", self.backend.read_attribute(&self.attribute("scaling_driver")))
+    }
+
+    /// Energy performance preference, see `Cpu::get_epp`
+    pub fn get_epp(&self) -> Result<Epp> {
+        self.read("energy_performance_preference", epp::read_epp(&*self.backend, &self.directory()))
+    }
+
+    pub fn get_available_epps(&self) -> Result<Vec<String>> {
+        self.read("energy_performance_available_preferences", epp::read_available_epps(&*self.backend, &self.directory()))
+    }
+
+    /// Set the energy performance preference of every cpu of the policy
+    pub fn set_epp<E: Into<Epp>>(&self, epp: E) -> Result<()> {
+        let epp = epp.into();
+        self.write("energy_performance_preference", &epp, epp::write_epp(&*self.backend, &self.directory(), &epp))
+    }
+
+    /// CPPC performance levels reported by the `amd-pstate` driver
+    pub fn get_amd_perf(&self) -> Result<AmdPerf> {
+        self.read(amd_pstate::PERF_ATTRIBUTES, amd_pstate::read_amd_perf(&*self.backend, &self.directory()))
+    }
+
+    pub fn get_policy(&self) -> Result<Policy> {
+        self.read(POLICY_ATTRIBUTES, backend::read_policy(&*self.backend, &self.directory()))
+    }
+
+    /// Set the limits and the governor of every cpu of the policy at once
+    pub fn set_policy(&self, policy: &Policy) -> Result<()> {
+        let result = backend::write_policy(&*self.backend, &self.directory(), policy)
+            .map_err(|error| explain_governor(&*self.backend, self.cpu(), &policy.governor, error));
+        self.write(POLICY_ATTRIBUTES, policy, result)
+    }
+
+    pub fn modify_policy_min(&self, min: Frequency) -> Result<()> {
+        self.write("scaling_min_freq", min, self.backend.write_attribute(&self.attribute("scaling_min_freq"), &min.to_string()))
+    }
+
+    pub fn modify_policy_max(&self, max: Frequency) -> Result<()> {
+        self.write("scaling_max_freq", max, self.backend.write_attribute(&self.attribute("scaling_max_freq"), &max.to_string()))
+    }
+
+    pub fn modify_policy_governor(&self, governor: &str) -> Result<()> {
+        let result = backend::write_governor(&*self.backend, &self.directory(), governor)
+            .map_err(|error| explain_governor(&*self.backend, self.cpu(), governor, error));
+        self.write("scaling_governor", governor, result)
+    }
+
+    pub fn get_available_governors(&self) -> Result<Vec<String>> {
+        self.read("scaling_available_governors", backend::read_words(&*self.backend, &self.attribute("scaling_available_governors")))
+    }
+
+    pub fn get_available_frequencies(&self) -> Result<Vec<Frequency>> {
+        self.read("scaling_available_frequencies", backend::read_list(&*self.backend, &self.attribute("scaling_available_frequencies")))
+    }
+
+    pub fn get_stats(&self) -> Result<Vec<Stat>> {
+        self.read("stats/time_in_state", backend::read_stats(&*self.backend, &self.directory()))
+    }
+
+    pub fn get_transitions(&self) -> Result<u64> {
+        self.read("stats/total_trans", backend::read_value(&*self.backend, &self.attribute("stats/total_trans")))
+    }
+
+    /// Wrap the error into `OperationFailed` telling what was attempted,
+    /// like `Cpu` does. Why an attribute is missing is asked to the first
+    /// online member, the first cpu might be offline while the policy
+    /// still serves the others.
+    fn check<T>(&self, operation: Operation, attribute: &str, value: Option<String>, result: Result<T>) -> Result<T> {
+        result.map_err(|error| {
+            let cpu = self.cpu();
+
+            CpuPowerError::OperationFailed {
+                context: ErrorContext {
+                    cpu: Some(cpu),
+                    policy: Some(self.id),
+                    operation,
+                    attribute: String::from(attribute),
+                    value
+                },
+                source: Box::new(explain_missing(&*self.backend, cpu, error))
+            }
+        })
+    }
+
+    /// First online member of the policy, its number when there is none
+    /// or the members can't be read
+    fn cpu(&self) -> CpuId {
+        backend::read_cpu_set(&*self.backend, &self.attribute("related_cpus")).ok()
+            .and_then(|cpus| cpus.iter().find(|id| self.backend.is_online(*id).unwrap_or(false)))
+            .unwrap_or(self.id)
+    }
+
+    fn read<T>(&self, attribute: &str, result: Result<T>) -> Result<T> {
+        self.check(Operation::Read, attribute, None, result)
+    }
+
+    fn write<T, V: fmt::Display>(&self, attribute: &str, value: V, result: Result<T>) -> Result<T> {
+        self.check(Operation::Write, attribute, Some(value.to_string()), result)
+    }
+
+    fn directory(&self) -> String {
+        backend::policy_directory(self.id)
+    }
+
+    fn attribute(&self, name: &str) -> String {
+        format!("{}/{}", self.directory(), name)
+    }
+}
+
+
+impl fmt::Display for FreqPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FreqPolicy{{id: {}, frequency: {}}}", self.id, self.get_freq().map(|freq| freq.to_string()).unwrap_or_else(|_| String::from("Unknown")))
+    }
+}
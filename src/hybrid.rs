@@ -0,0 +1,204 @@
+//! # Heterogeneous cpus
+//!
+//! big.LITTLE and hybrid systems mix cpus of different performance, e.g.
+//! efficiency and performance cores. `CoreClasses` sorts the present cpus
+//! into classes by their `cpu_capacity` when the kernel exposes it, by the
+//! highest frequency of their policy (`cpuinfo_max_freq`) otherwise. The
+//! frequency limits are read once per policy, cpus sharing a policy always
+//! end up in the same class unless their capacities differ.
+//!
+//! Highest frequencies alone only split cpus when they are far apart: the
+//! favored cores of Intel Turbo Boost Max 3.0 boost a little higher than
+//! the others without being another kind of core.
+
+use ::backend::{Backend, default_backend};
+use ::cpu::Cpu;
+use ::cpu_set::CpuSet;
+use ::freq_policy::FreqPolicy;
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+use ::types::{CpuId, CpuMask, Frequency};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+/// Without `cpu_capacity`, cpus are of another class when their highest
+/// frequency is that much (in percent) above the next lower one
+const FREQUENCY_GAP_PERCENT: Frequency = 20;
+
+
+/// Cpus of the same performance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreClass {
+    /// `cpu_capacity` of the cpus, `None` when the kernel doesn't expose it
+    pub capacity: Option<u64>,
+    /// Lowest and highest hardware frequency of the cpus, in kHz
+    pub hardware_limits: Option<(Frequency, Frequency)>,
+    pub cpus: CpuSet,
+    /// N of the `cpufreq/policyN` the cpus belong to
+    pub policies: Vec<u32>
+}
+
+
+#[derive(Debug, Clone)]
+pub struct CoreClasses {
+    /// Slowest first
+    classes: Vec<CoreClass>,
+    /// Cpus with neither a capacity nor hardware limits
+    unclassified: CpuSet,
+    backend: Arc<dyn Backend>
+}
+
+
+impl CoreClasses {
+    /// Classes of the present cpus of your system
+    pub fn detect() -> Result<CoreClasses> {
+        CoreClasses::detect_with(default_backend())
+    }
+
+    /// Classes of the present cpus of the given sysfs tree
+    pub fn detect_in(root: &SysfsRoot) -> Result<CoreClasses> {
+        CoreClasses::detect_with(Arc::new(root.clone()))
+    }
+
+    /// Classes of the present cpus according to the given backend.
+    ///
+    /// Without `cpu_capacity` cpus are put in different classes only when
+    /// their highest frequencies differ by more than 20%.
+    pub fn detect_with(backend: Arc<dyn Backend>) -> Result<CoreClasses> {
+        let mut classes: BTreeMap<(Option<u64>, Option<Frequency>), CoreClass> = BTreeMap::new();
+        let mut unclassified = CpuSet::new();
+        let mut remaining = backend.get_cpus(CpuMask::Present)?;
+        let mut policies = Vec::new();
+
+        while let Some(first) = remaining.first() {
+            let related = backend.get_related_cpus(first).unwrap_or_default();
+            let mut policy = related.intersection(&remaining);
+            policy.insert(first);
+            remaining = remaining.difference(&policy);
+
+            // policyN is named after the cpu that brought it up, not
+            // necessarily the first one
+            let policy_id = FreqPolicy::of_cpu(&Cpu::with_backend(backend.clone(), first)).ok().map(|policy| policy.get_id());
+            let limits = backend.get_hardware_limits(first).ok();
+            policies.push((policy, policy_id, limits));
+        }
+
+        let bands = frequency_bands(policies.iter().filter_map(|policy| policy.2).map(|limits| limits.1).collect());
+
+        for (policy, policy_id, limits) in policies {
+            for id in &policy {
+                let capacity = backend.get_capacity(id).ok();
+
+                if capacity.is_none() && limits.is_none() {
+                    unclassified.insert(id);
+                    continue;
+                }
+
+                let band = limits.and_then(|limits| bands.get(&limits.1).cloned());
+                let key = (capacity, if capacity.is_some() { None } else { band });
+                let class = classes.entry(key).or_insert_with(|| CoreClass {
+                    capacity,
+                    hardware_limits: None,
+                    cpus: CpuSet::new(),
+                    policies: Vec::new()
+                });
+
+                class.cpus.insert(id);
+                class.hardware_limits = widen(class.hardware_limits, limits);
+
+                if let Some(policy_id) = policy_id {
+                    if limits.is_some() && !class.policies.contains(&policy_id) {
+                        class.policies.push(policy_id);
+                    }
+                }
+            }
+        }
+
+        let mut classes: Vec<CoreClass> = classes.into_values().collect();
+        classes.sort_by_key(|class| (class.capacity, class.hardware_limits.map(|limits| limits.1)));
+
+        Ok(CoreClasses { classes, unclassified, backend })
+    }
+
+    /// Every class, slowest first
+    pub fn classes(&self) -> &[CoreClass] {
+        &self.classes
+    }
+
+    /// Whether the cpus are of more than one class
+    pub fn is_hybrid(&self) -> bool {
+        self.classes.len() > 1
+    }
+
+    /// Cpus of the slowest class, none unless the system is hybrid
+    pub fn efficiency_cores(&self) -> CpuSet {
+        match self.classes.first() {
+            Some(class) if self.is_hybrid() => class.cpus.clone(),
+            _ => CpuSet::new()
+        }
+    }
+
+    /// Cpus of every class but the slowest, all of them unless the system
+    /// is hybrid
+    pub fn performance_cores(&self) -> CpuSet {
+        let all = self.classes.iter().fold(CpuSet::new(), |all, class| all.union(&class.cpus));
+        all.difference(&self.efficiency_cores())
+    }
+
+    /// Class of the cpu, `None` if it wasn't classified
+    pub fn class_of(&self, id: CpuId) -> Option<&CoreClass> {
+        self.classes.iter().find(|class| class.cpus.contains(id))
+    }
+
+    /// Cpus whose capacity and frequencies are unknown, e.g. cpus without
+    /// a cpufreq driver
+    pub fn unclassified(&self) -> &CpuSet {
+        &self.unclassified
+    }
+
+    /// Handles for the cpus of the set, sharing the backend of the classes
+    pub fn cpus(&self, cpus: &CpuSet) -> Vec<Cpu> {
+        cpus.to_cpus_with(self.backend.clone())
+    }
+
+    /// Policies of the class, to change the limits of all its cpus
+    pub fn policies(&self, class: &CoreClass) -> Vec<FreqPolicy> {
+        class.policies.iter().map(|id| FreqPolicy::with_backend(self.backend.clone(), *id)).collect()
+    }
+}
+
+
+/// Lowest highest frequency of the band of every highest frequency, a band
+/// starting anew at a frequency more than `FREQUENCY_GAP_PERCENT` above
+/// the previous one
+fn frequency_bands(mut frequencies: Vec<Frequency>) -> BTreeMap<Frequency, Frequency> {
+    frequencies.sort();
+    frequencies.dedup();
+
+    let mut bands = BTreeMap::new();
+    let mut previous: Option<(Frequency, Frequency)> = None;
+
+    for freq in frequencies {
+        let band = match previous {
+            Some((band, last)) if freq <= last + last * FREQUENCY_GAP_PERCENT / 100 => band,
+            _ => freq
+        };
+
+        bands.insert(freq, band);
+        previous = Some((band, freq));
+    }
+
+    bands
+}
+
+
+/// Limits covering both
+fn widen(limits: Option<(Frequency, Frequency)>, other: Option<(Frequency, Frequency)>) -> Option<(Frequency, Frequency)> {
+    match (limits, other) {
+        (Some(limits), Some(other)) => Some((limits.0.min(other.0), limits.1.max(other.1))),
+        (limits, other) => limits.or(other)
+    }
+}
@@ -0,0 +1,192 @@
+//! # intel_pstate driver
+//!
+//! Besides the usual cpufreq attributes, `intel_pstate` has global knobs in
+//! `/sys/devices/system/cpu/intel_pstate`: the operation mode of the driver
+//! and limits applying to every cpu, in percent of the highest performance.
+//! `IntelPstate` reads and writes them. Writing needs root privileges and
+//! most of them are only there while the driver is `active` or `passive`.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend, default_backend};
+use ::error::{CpuPowerError, Operation};
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+
+use std::fmt;
+use std::str::FromStr;
+use std::string::String;
+use std::sync::Arc;
+
+
+const DIRECTORY: &str = "intel_pstate";
+
+
+/// Operation mode of the driver, from `intel_pstate/status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PstateStatus {
+    /// The driver picks the frequencies itself (or lets the hardware do it
+    /// with HWP), only the `performance` and `powersave` governors exist
+    Active,
+    /// The driver acts like any other cpufreq driver, `intel_cpufreq`,
+    /// under the generic governors
+    Passive,
+    /// The driver is unregistered
+    Off
+}
+
+
+#[derive(Debug, Clone)]
+pub struct IntelPstate {
+    backend: Arc<dyn Backend>
+}
+
+
+impl PstateStatus {
+    /// Name used by the kernel
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PstateStatus::Active => "active",
+            PstateStatus::Passive => "passive",
+            PstateStatus::Off => "off"
+        }
+    }
+}
+
+
+impl fmt::Display for PstateStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+impl FromStr for PstateStatus {
+    type Err = CpuPowerError;
+
+    fn from_str(name: &str) -> Result<PstateStatus> {
+        match name.trim() {
+            "active" => Ok(PstateStatus::Active),
+            "passive" => Ok(PstateStatus::Passive),
+            "off" => Ok(PstateStatus::Off),
+            _ => Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+        }
+    }
+}
+
+
+impl IntelPstate {
+    /// Driver of your system
+    pub fn new() -> IntelPstate {
+        IntelPstate::with_backend(default_backend())
+    }
+
+    /// Driver of the given sysfs tree
+    pub fn new_in(root: &SysfsRoot) -> IntelPstate {
+        IntelPstate::with_backend(Arc::new(root.clone()))
+    }
+
+    pub fn with_backend(backend: Arc<dyn Backend>) -> IntelPstate {
+        IntelPstate {
+            backend
+        }
+    }
+
+    /// Whether `intel_pstate` is there at all, even if `off`
+    pub fn is_available(&self) -> bool {
+        self.backend.list_directory(DIRECTORY).is_ok()
+    }
+
+    pub fn get_status(&self) -> Result<PstateStatus> {
+        self.read("status", self.backend.read_attribute(&attribute("status")).and_then(|status| status.parse()))
+    }
+
+    /// Switch the driver to another mode, the policies are recreated and
+    /// lose their settings
+    pub fn set_status(&self, status: PstateStatus) -> Result<()> {
+        self.write("status", status, self.backend.write_attribute(&attribute("status"), status.name()))
+    }
+
+    /// Lowest performance allowed to any cpu, in percent of the highest
+    pub fn get_min_perf_pct(&self) -> Result<u32> {
+        self.read("min_perf_pct", backend::read_value(&*self.backend, &attribute("min_perf_pct")))
+    }
+
+    pub fn set_min_perf_pct(&self, percent: u32) -> Result<()> {
+        self.write_percent("min_perf_pct", percent)
+    }
+
+    /// Highest performance allowed to any cpu, in percent of the highest
+    pub fn get_max_perf_pct(&self) -> Result<u32> {
+        self.read("max_perf_pct", backend::read_value(&*self.backend, &attribute("max_perf_pct")))
+    }
+
+    pub fn set_max_perf_pct(&self, percent: u32) -> Result<()> {
+        self.write_percent("max_perf_pct", percent)
+    }
+
+    /// Whether turbo frequencies are disabled
+    pub fn get_no_turbo(&self) -> Result<bool> {
+        self.read("no_turbo", backend::read_flag(&*self.backend, &attribute("no_turbo")))
+    }
+
+    /// Disable (`true`) or allow turbo frequencies. The kernel refuses to
+    /// allow them when the firmware disabled turbo.
+    pub fn set_no_turbo(&self, no_turbo: bool) -> Result<()> {
+        let value = if no_turbo { "1" } else { "0" };
+        self.write("no_turbo", value, self.backend.write_attribute(&attribute("no_turbo"), value))
+    }
+
+    /// Whether HWP raises the minimum performance of cpus waking up after
+    /// waiting for I/O, only with HWP in active mode
+    pub fn get_hwp_dynamic_boost(&self) -> Result<bool> {
+        self.read("hwp_dynamic_boost", backend::read_flag(&*self.backend, &attribute("hwp_dynamic_boost")))
+    }
+
+    pub fn set_hwp_dynamic_boost(&self, boost: bool) -> Result<()> {
+        let value = if boost { "1" } else { "0" };
+        self.write("hwp_dynamic_boost", value, self.backend.write_attribute(&attribute("hwp_dynamic_boost"), value))
+    }
+
+    /// Number of P-states supported by the hardware, turbo ones included
+    pub fn get_num_pstates(&self) -> Result<u32> {
+        self.read("num_pstates", backend::read_value(&*self.backend, &attribute("num_pstates")))
+    }
+
+    /// Share of the P-states in the turbo range, in percent
+    pub fn get_turbo_pct(&self) -> Result<u32> {
+        self.read("turbo_pct", backend::read_value(&*self.backend, &attribute("turbo_pct")))
+    }
+
+    fn write_percent(&self, name: &str, percent: u32) -> Result<()> {
+        let result = if percent > 100 {
+            Err(CpuPowerError::from(errno::Errno(libc::EINVAL)))
+        } else {
+            self.backend.write_attribute(&attribute(name), &percent.to_string())
+        };
+
+        self.write(name, percent, result)
+    }
+
+    /// Wrap the error into `OperationFailed` telling which knob was read
+    fn read<T>(&self, name: &str, result: Result<T>) -> Result<T> {
+        backend::check_global(Operation::Read, &attribute(name), None, result)
+    }
+
+    fn write<T, V: fmt::Display>(&self, name: &str, value: V, result: Result<T>) -> Result<T> {
+        backend::check_global(Operation::Write, &attribute(name), Some(value.to_string()), result)
+    }
+}
+
+
+impl Default for IntelPstate {
+    fn default() -> IntelPstate {
+        IntelPstate::new()
+    }
+}
+
+
+fn attribute(name: &str) -> String {
+    format!("{}/{}", DIRECTORY, name)
+}
@@ -4,18 +4,60 @@
 //!
 //! The main entity to be used is [`Cpu`](./struct.Cpu.html) struct.
 
+#[cfg(not(cpufreq = "sysfs"))]
 mod adapters;
+#[cfg(not(cpufreq = "sysfs"))]
 mod base;
+#[cfg(not(cpufreq = "sysfs"))]
+mod libcpupower;
+mod amd_pstate;
+mod backend;
+mod boost;
+mod bulk;
 mod cpu;
+mod cpu_set;
+mod energy_perf_bias;
+mod epp;
+mod memory;
 mod policy;
 mod result;
+mod setspeed;
+mod simulator;
 mod error;
+mod fixture;
+mod freq_policy;
+mod hybrid;
+mod intel_pstate;
+mod sysfs;
+mod topology;
+mod tunables;
+#[cfg(test)]
 mod test;
 mod types;
 mod stat;
 
 
 pub use types::*;
+pub use amd_pstate::{AmdPerf, AmdPstate, AmdPstateStatus};
 pub use cpu::*;
+pub use cpu_set::CpuSet;
+pub use energy_perf_bias::EnergyPerfBias;
+pub use epp::Epp;
 pub use policy::*;
 pub use error::*;
+pub use sysfs::SysfsRoot;
+pub use backend::{ActiveBackend, Backend, active_backend};
+pub use boost::{Boost, BoostMechanism};
+pub use bulk::{Bulk, Report};
+pub use fixture::Fixture;
+pub use freq_policy::FreqPolicy;
+pub use hybrid::{CoreClass, CoreClasses};
+pub use intel_pstate::{IntelPstate, PstateStatus};
+pub use memory::{CpuState, InMemory};
+pub use setspeed::SetSpeed;
+pub use simulator::{SimulatedPolicy, Simulator};
+pub use stat::Stat;
+pub use topology::{CpuTopology, Topology};
+pub use tunables::{GovernorTunables, Tunable, TunablesScope};
+#[cfg(not(cpufreq = "sysfs"))]
+pub use libcpupower::LibCpupower;
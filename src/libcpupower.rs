@@ -0,0 +1,217 @@
+//! # Access to the cpufreq subsystem through libcpupower (or libcpufreq)
+//!
+//! Thin safe wrappers around the functions declared in `base`.
+//!
+//! Every call clears errno before and captures it right after the library
+//! function returns (see `call`), so errors reported to one thread are
+//! never those of a call made by another one. The library itself keeps no
+//! shared state, `LibCpupower` is `Send` and `Sync`.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::Backend;
+use ::base::*;
+use ::cpu_set::CpuSet;
+use ::error::CpuPowerError;
+use ::policy::Policy;
+use ::stat::Stat;
+use ::sysfs::SysfsRoot;
+use ::result::Result;
+use ::types::{CpuId, Frequency};
+use ::adapters::Extract;
+
+use std::os::raw::c_char;
+use std::ffi::{CStr, CString};
+use std::str;
+use std::string::String;
+use std::vec::Vec;
+
+
+/// Backend calling the library linked by the build script (or loaded at
+/// runtime with the `dynamic` feature)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibCpupower;
+
+
+impl Backend for LibCpupower {
+    /// libcpupower has no generic attribute access, sysfs is used directly
+    fn read_attribute(&self, path: &str) -> Result<String> {
+        SysfsRoot::default().read_attribute(path)
+    }
+
+    fn write_attribute(&self, path: &str, value: &str) -> Result<()> {
+        SysfsRoot::default().write_attribute(path, value)
+    }
+
+    fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+        SysfsRoot::default().list_directory(path)
+    }
+
+    fn cpu_exists(&self, id: CpuId) -> bool {
+        unsafe {
+            cpufreq_cpu_exists(id) == 0
+        }
+    }
+
+    fn get_freq_kernel(&self, id: CpuId) -> Result<Frequency> {
+        check_value(call(|| unsafe { cpufreq_get_freq_kernel(id) }))
+    }
+
+    fn get_freq_hardware(&self, id: CpuId) -> Result<Frequency> {
+        check_value(call(|| unsafe { cpufreq_get_freq_hardware(id) }))
+    }
+
+    fn set_frequency(&self, id: CpuId, freq: Frequency) -> Result<()> {
+        check_status(call(|| unsafe { cpufreq_set_frequency(id, freq) }))
+    }
+
+    fn get_transition_latency(&self, id: CpuId) -> Result<u64> {
+        check_value(call(|| unsafe { cpufreq_get_transition_latency(id) }))
+    }
+
+    fn modify_policy_max(&self, id: CpuId, max: Frequency) -> Result<()> {
+        check_status(call(|| unsafe { cpufreq_modify_policy_max(id, max) }))
+    }
+
+    fn modify_policy_min(&self, id: CpuId, min: Frequency) -> Result<()> {
+        check_status(call(|| unsafe { cpufreq_modify_policy_min(id, min) }))
+    }
+
+    fn modify_policy_governor(&self, id: CpuId, governor: &str) -> Result<()> {
+        let governor = CString::new(governor)?;
+        check_status(call(|| unsafe {
+            cpufreq_modify_policy_governor(id, governor.as_ptr() as *mut libc::c_char)
+        }))
+    }
+
+    fn get_hardware_limits(&self, id: CpuId) -> Result<(Frequency, Frequency)> {
+        let mut min: u64 = 0;
+        let mut max: u64 = 0;
+
+        check_status(call(|| unsafe {
+            cpufreq_get_hardware_limits(id, &mut min as *mut u64, &mut max as *mut u64)
+        }))?;
+
+        Ok((min, max))
+    }
+
+    fn get_driver(&self, id: CpuId) -> Result<String> {
+        let driver_name_ptr: *mut c_char = check_pointer(call(|| unsafe { cpufreq_get_driver(id) }))?;
+
+        unsafe {
+            let driver_name: CString = CStr::from_ptr(driver_name_ptr).to_owned();
+            cpufreq_put_driver(driver_name_ptr);
+
+            Ok(String::from_utf8(driver_name.into_bytes())?)
+        }
+    }
+
+    fn get_policy(&self, id: CpuId) -> Result<Policy> {
+        let policy = check_pointer(call(|| unsafe { cpufreq_get_policy(id) }))?;
+
+        unsafe {
+            let min = (*policy).min;
+            let max = (*policy).max;
+
+            let result = str::from_utf8(CStr::from_ptr((*policy).governor).to_bytes())
+                .map(|governor_name| Policy::new(min, max, governor_name))
+                .map_err(CpuPowerError::Utf8Error);
+
+            cpufreq_put_policy(policy);
+
+            result
+        }
+    }
+
+    fn set_policy(&self, id: CpuId, policy: &Policy) -> Result<()> {
+        let governor_name = CString::new(policy.governor.as_str())?;
+        let mut policy = Struct_cpufreq_policy{
+            min: policy.min,
+            max: policy.max,
+            governor: governor_name.as_ptr() as *mut libc::c_char
+        };
+
+        check_status(call(|| unsafe {
+            cpufreq_set_policy(id, &mut policy as *mut Struct_cpufreq_policy)
+        }))
+    }
+
+    fn get_available_governors(&self, id: CpuId) -> Result<Vec<String>> {
+        ::adapters::AvailableGovernors::extract(id)
+    }
+
+    fn get_available_frequencies(&self, id: CpuId) -> Result<Vec<Frequency>> {
+        ::adapters::AvailableFrequencies::extract(id)
+    }
+
+    fn get_affected_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        ::adapters::AffectedCpus::extract(id).map(|ids| ids.into_iter().collect())
+    }
+
+    fn get_related_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        ::adapters::RelatedCpus::extract(id).map(|ids| ids.into_iter().collect())
+    }
+
+    fn get_stats(&self, id: CpuId) -> Result<Vec<Stat>> {
+        ::adapters::Stats::extract(id)
+    }
+
+    /// Zero is a valid count, so it is an error only if errno got set by the call
+    fn get_transitions(&self, id: CpuId) -> Result<u64> {
+        match call(|| unsafe { cpufreq_get_transitions(id) }) {
+            (0, errno) if errno.0 != 0 => Err(CpuPowerError::from(errno)),
+            (transitions, _) => Ok(transitions)
+        }
+    }
+}
+
+
+/// Make an FFI call with errno cleared beforehand and return errno as the
+/// call left it. errno is thread local and is read right after the call,
+/// so whatever other threads do in the meantime can't affect the result.
+pub fn call<T, F: FnOnce() -> T>(f: F) -> (T, errno::Errno) {
+    errno::set_errno(errno::Errno(0));
+    let result = f();
+    (result, errno::errno())
+}
+
+
+/// Error of a call which failed without setting errno
+fn failure(errno: errno::Errno, code: libc::c_int) -> CpuPowerError {
+    if errno.0 != 0 {
+        CpuPowerError::from(errno)
+    } else if code < 0 {
+        CpuPowerError::from(errno::Errno(-code))
+    } else {
+        CpuPowerError::Unknown
+    }
+}
+
+
+/// Functions returning 0 on success and an error (often `-errno`) otherwise
+fn check_status((status, errno): (libc::c_int, errno::Errno)) -> Result<()> {
+    match status {
+        0 => Ok(()),
+        _ => Err(failure(errno, status))
+    }
+}
+
+
+/// Functions returning a value, 0 meaning failure
+fn check_value((value, errno): (libc::c_ulong, errno::Errno)) -> Result<u64> {
+    match value {
+        0 => Err(failure(errno, 0)),
+        _ => Ok(value)
+    }
+}
+
+
+/// Functions returning a pointer, null meaning failure
+pub fn check_pointer<T>((pointer, errno): (*mut T, errno::Errno)) -> Result<*mut T> {
+    if pointer.is_null() {
+        Err(failure(errno, 0))
+    } else {
+        Ok(pointer)
+    }
+}
@@ -0,0 +1,344 @@
+//! # In-memory cpufreq model
+//!
+//! Keeps the state of every cpu in a map, so a `Cpu` can be exercised
+//! without any hardware, library or sysfs tree behind it.
+//!
+//! This is a plain per-cpu stub: nothing is shared between related cpus
+//! and no governor runs, a write to one cpu leaves the others alone. Like
+//! sysfs, writes that would make the state inconsistent (min greater than
+//! max, a governor missing from `scaling_available_governors`) are
+//! rejected with EINVAL. `Simulator` models policies and governors.
+//!
+//! The `cpuN/cpufreq` and `cpufreq/policyN` attributes are built from
+//! `CpuState`, so the cpufreq operations and the attribute level methods
+//! see the same state. Other raw attributes (e.g. driver specific ones)
+//! are added with `with_attribute`.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend};
+use ::cpu_set::CpuSet;
+use ::error::CpuPowerError;
+use ::policy::Policy;
+use ::result::Result;
+use ::stat::Stat;
+use ::types::{CpuId, Frequency};
+
+use std::collections::BTreeMap;
+use std::iter;
+use std::string::String;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+
+/// Everything the backend knows about one cpu
+#[derive(Debug, Clone)]
+pub struct CpuState {
+    pub freq: Frequency,
+    pub hardware_limits: (Frequency, Frequency),
+    pub transition_latency: u64,
+    pub driver: String,
+    pub policy: Policy,
+    pub available_governors: Vec<String>,
+    pub available_frequencies: Vec<Frequency>,
+    pub affected_cpus: CpuSet,
+    pub related_cpus: CpuSet,
+    pub stats: Vec<Stat>,
+    pub transitions: u64
+}
+
+
+impl CpuState {
+    /// State of a standalone acpi-cpufreq cpu running ondemand
+    pub fn new(id: CpuId) -> CpuState {
+        CpuState {
+            freq: 2400000,
+            hardware_limits: (800000, 2400000),
+            transition_latency: 10000,
+            driver: String::from("acpi-cpufreq"),
+            policy: Policy::new(800000, 2400000, "ondemand"),
+            available_governors: ["conservative", "ondemand", "userspace", "powersave", "performance"]
+                .iter().map(|governor| String::from(*governor)).collect(),
+            available_frequencies: vec![2400000, 1600000, 800000],
+            affected_cpus: iter::once(id).collect(),
+            related_cpus: iter::once(id).collect(),
+            stats: vec![],
+            transitions: 0
+        }
+    }
+
+    /// Content of an attribute under `cpuN/cpufreq`, as the kernel formats it
+    fn attribute(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "affected_cpus" => backend::format_list(self.affected_cpus.iter()),
+            "related_cpus" => backend::format_list(self.related_cpus.iter()),
+            "cpuinfo_cur_freq" | "scaling_cur_freq" => self.freq.to_string(),
+            "cpuinfo_max_freq" => self.hardware_limits.1.to_string(),
+            "cpuinfo_min_freq" => self.hardware_limits.0.to_string(),
+            "cpuinfo_transition_latency" => self.transition_latency.to_string(),
+            "scaling_available_frequencies" => backend::format_list(&self.available_frequencies),
+            "scaling_available_governors" => backend::format_list(&self.available_governors),
+            "scaling_driver" => self.driver.clone(),
+            "scaling_governor" => self.policy.governor.clone(),
+            "scaling_max_freq" => self.policy.max.to_string(),
+            "scaling_min_freq" => self.policy.min.to_string(),
+            "scaling_setspeed" => backend::format_setspeed(&self.policy.governor, self.freq),
+            "stats/time_in_state" => backend::format_stats(&self.stats),
+            "stats/total_trans" => self.transitions.to_string(),
+            _ => return None
+        };
+
+        Some(value)
+    }
+
+    /// Write an attribute under `cpuN/cpufreq`. Like the kernel,
+    /// `scaling_setspeed` is refused unless the userspace governor is in
+    /// charge and read-only attributes with EACCES.
+    fn set_attribute(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+
+        match name {
+            "scaling_max_freq" => return self.set_max(backend::parse_frequency(value)?),
+            "scaling_min_freq" => return self.set_min(backend::parse_frequency(value)?),
+            "scaling_governor" => return self.set_governor(value),
+            "scaling_setspeed" if self.policy.governor == "userspace" => self.freq = backend::parse_frequency(value)?,
+            "scaling_setspeed" => return Err(CpuPowerError::from(errno::Errno(libc::EINVAL))),
+            _ => return Err(CpuPowerError::from(errno::Errno(libc::EACCES)))
+        }
+
+        Ok(())
+    }
+
+    fn set_min(&mut self, min: Frequency) -> Result<()> {
+        if min > self.policy.max {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.policy.min = min;
+        Ok(())
+    }
+
+    fn set_max(&mut self, max: Frequency) -> Result<()> {
+        if max < self.policy.min {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.policy.max = max;
+        Ok(())
+    }
+
+    fn set_governor(&mut self, governor: &str) -> Result<()> {
+        if !self.available_governors.iter().any(|available| available == governor) {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.policy.governor = String::from(governor);
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Default)]
+pub struct InMemory {
+    cpus: Mutex<BTreeMap<CpuId, CpuState>>,
+    attributes: Mutex<BTreeMap<String, String>>
+}
+
+
+impl InMemory {
+    pub fn new() -> InMemory {
+        InMemory::default()
+    }
+
+    /// Add a cpu with the given state, replacing the existing one
+    pub fn with_cpu(self, id: CpuId, state: CpuState) -> InMemory {
+        self.insert(id, state);
+        self
+    }
+
+    pub fn insert(&self, id: CpuId, state: CpuState) {
+        self.lock().insert(id, state);
+    }
+
+    /// Add a raw attribute, `path` is relative to `/sys/devices/system/cpu`
+    pub fn with_attribute(self, path: &str, value: &str) -> InMemory {
+        self.lock_attributes().insert(String::from(path.trim_matches('/')), String::from(value));
+        self
+    }
+
+    /// Snapshot of the current state of the cpu
+    pub fn state(&self, id: CpuId) -> Option<CpuState> {
+        self.lock().get(&id).cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<CpuId, CpuState>> {
+        self.cpus.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_attributes(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.attributes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Cpu whose state holds the attribute and the attribute name, for
+    /// both `cpuN/cpufreq/...` and `cpufreq/policyN/...` paths. `policyN`
+    /// belongs to cpu N when it is the first of its related cpus.
+    fn locate<'a>(&self, path: &'a str) -> Option<(CpuId, &'a str)> {
+        let (id, name) = match backend::parse_cpufreq_attribute(path) {
+            Some(attribute) => attribute,
+            None => {
+                let (number, name) = backend::parse_policy_attribute(path)?;
+
+                if self.read(number, |state| state.related_cpus.first()).ok()? != Some(number) {
+                    return None;
+                }
+
+                (number, name)
+            }
+        };
+
+        if self.cpu_exists(id) && backend::CPUFREQ_ATTRIBUTE_NAMES.contains(&name) {
+            Some((id, name))
+        } else {
+            None
+        }
+    }
+
+    fn read<T, F: FnOnce(&CpuState) -> T>(&self, id: CpuId, f: F) -> Result<T> {
+        self.lock().get(&id).map(f).ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+
+    fn write<F: FnOnce(&mut CpuState) -> Result<()>>(&self, id: CpuId, f: F) -> Result<()> {
+        match self.lock().get_mut(&id) {
+            Some(state) => f(state),
+            None => Err(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+        }
+    }
+}
+
+
+impl Backend for InMemory {
+    fn read_attribute(&self, path: &str) -> Result<String> {
+        if let Some((id, name)) = self.locate(path) {
+            if let Some(value) = self.read(id, |state| state.attribute(name))? {
+                return Ok(value);
+            }
+        }
+
+        self.lock_attributes().get(path.trim_matches('/')).cloned()
+            .ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+
+    /// Only attributes of a cpu or added beforehand can be written, like in sysfs
+    fn write_attribute(&self, path: &str, value: &str) -> Result<()> {
+        if let Some((id, name)) = self.locate(path) {
+            return self.write(id, |state| state.set_attribute(name, value));
+        }
+
+        match self.lock_attributes().get_mut(path.trim_matches('/')) {
+            Some(existing) => {
+                *existing = String::from(value.trim_end());
+                Ok(())
+            },
+            None => Err(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+        }
+    }
+
+    fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+        let mut paths: Vec<String> = self.lock_attributes().keys().cloned().collect();
+
+        for (id, state) in self.lock().iter() {
+            let mut directories = vec![backend::cpufreq_directory(*id)];
+
+            if state.related_cpus.first() == Some(*id) {
+                directories.push(backend::policy_directory(*id));
+            }
+
+            for directory in directories {
+                paths.extend(backend::CPUFREQ_ATTRIBUTE_NAMES.iter().map(|name| format!("{}/{}", directory, name)));
+            }
+        }
+
+        backend::list_children(&paths, path)
+    }
+
+    fn cpu_exists(&self, id: CpuId) -> bool {
+        self.lock().contains_key(&id)
+    }
+
+    fn list_cpus(&self) -> Result<CpuSet> {
+        Ok(self.lock().keys().cloned().collect())
+    }
+
+    fn get_freq_kernel(&self, id: CpuId) -> Result<Frequency> {
+        self.read(id, |state| state.freq)
+    }
+
+    fn get_freq_hardware(&self, id: CpuId) -> Result<Frequency> {
+        self.read(id, |state| state.freq)
+    }
+
+    fn get_transition_latency(&self, id: CpuId) -> Result<u64> {
+        self.read(id, |state| state.transition_latency)
+    }
+
+    fn get_hardware_limits(&self, id: CpuId) -> Result<(Frequency, Frequency)> {
+        self.read(id, |state| state.hardware_limits)
+    }
+
+    fn get_driver(&self, id: CpuId) -> Result<String> {
+        self.read(id, |state| state.driver.clone())
+    }
+
+    fn get_policy(&self, id: CpuId) -> Result<Policy> {
+        self.read(id, |state| state.policy.clone())
+    }
+
+    /// Nothing changes unless the whole policy is valid
+    fn set_policy(&self, id: CpuId, policy: &Policy) -> Result<()> {
+        self.write(id, |state| {
+            if policy.max < policy.min || !state.available_governors.contains(&policy.governor) {
+                return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+            }
+
+            state.policy = policy.clone();
+            Ok(())
+        })
+    }
+
+    fn modify_policy_min(&self, id: CpuId, min: Frequency) -> Result<()> {
+        self.write(id, |state| state.set_min(min))
+    }
+
+    fn modify_policy_max(&self, id: CpuId, max: Frequency) -> Result<()> {
+        self.write(id, |state| state.set_max(max))
+    }
+
+    fn modify_policy_governor(&self, id: CpuId, governor: &str) -> Result<()> {
+        self.write(id, |state| state.set_governor(governor))
+    }
+
+    fn get_available_governors(&self, id: CpuId) -> Result<Vec<String>> {
+        self.read(id, |state| state.available_governors.clone())
+    }
+
+    fn get_available_frequencies(&self, id: CpuId) -> Result<Vec<Frequency>> {
+        self.read(id, |state| state.available_frequencies.clone())
+    }
+
+    fn get_affected_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        self.read(id, |state| state.affected_cpus.clone())
+    }
+
+    fn get_related_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        self.read(id, |state| state.related_cpus.clone())
+    }
+
+    fn get_stats(&self, id: CpuId) -> Result<Vec<Stat>> {
+        self.read(id, |state| state.stats.clone())
+    }
+
+    fn get_transitions(&self, id: CpuId) -> Result<u64> {
+        self.read(id, |state| state.transitions)
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Policy {
     pub min: u64,
     pub max: u64,
@@ -10,8 +10,8 @@ pub struct Policy {
 impl Policy {
     pub fn new(min: u64, max: u64, governor: &str) -> Policy {
         let mut result = Policy {
-            min: min,
-            max: max,
+            min,
+            max,
             governor: String::new()
         };
 
@@ -0,0 +1,73 @@
+//! # Fixed frequency with the userspace governor
+//!
+//! `scaling_setspeed` only takes a frequency while the `userspace`
+//! governor is in charge. `Cpu::set_freq` switches to it silently, like
+//! libcpupower; the functions here refuse to unless asked to and tell
+//! which governor was replaced so it can be restored.
+
+use ::backend::{self, Backend};
+use ::error::CpuPowerError;
+use ::result::Result;
+use ::types::Frequency;
+
+use std::string::String;
+
+
+const USERSPACE: &str = "userspace";
+
+
+/// Outcome of setting the frequency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetSpeed {
+    /// Governor in charge before, to restore it later
+    pub previous_governor: String,
+    pub frequency: Frequency
+}
+
+
+impl SetSpeed {
+    /// Whether the governor was switched to userspace on the way
+    pub fn switched_governor(&self) -> bool {
+        self.previous_governor != USERSPACE
+    }
+}
+
+
+/// Frequency requested through `scaling_setspeed` of a cpufreq directory,
+/// `GovernorMismatch` unless the userspace governor is in charge
+pub fn read_setspeed<B: Backend + ?Sized>(backend: &B, directory: &str) -> Result<Frequency> {
+    let governor = backend.read_attribute(&format!("{}/scaling_governor", directory))?;
+
+    if governor != USERSPACE {
+        return Err(CpuPowerError::GovernorMismatch { governor, required: USERSPACE });
+    }
+
+    backend::read_value(backend, &format!("{}/scaling_setspeed", directory))
+}
+
+
+/// Write `scaling_setspeed`. Another governor than userspace is an error
+/// unless `switch_governor` is set; if the frequency is then refused the
+/// previous governor is put back.
+pub fn write_setspeed<B: Backend + ?Sized>(backend: &B, directory: &str, frequency: Frequency, switch_governor: bool) -> Result<SetSpeed> {
+    let previous_governor = backend.read_attribute(&format!("{}/scaling_governor", directory))?;
+    let result = SetSpeed { previous_governor, frequency };
+
+    if result.switched_governor() {
+        if !switch_governor {
+            return Err(CpuPowerError::GovernorMismatch { governor: result.previous_governor, required: USERSPACE });
+        }
+
+        backend::write_governor(backend, directory, USERSPACE)?;
+    }
+
+    if let Err(error) = backend.write_attribute(&format!("{}/scaling_setspeed", directory), &frequency.to_string()) {
+        if result.switched_governor() {
+            let _ = backend::write_governor(backend, directory, &result.previous_governor);
+        }
+
+        return Err(error);
+    }
+
+    Ok(result)
+}
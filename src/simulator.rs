@@ -0,0 +1,503 @@
+//! # Simulated cpufreq kernel subsystem
+//!
+//! Unlike `InMemory`, which keeps a plain state per cpu, the simulator
+//! behaves like the kernel does: cpus are grouped into policies, limits
+//! are clamped to the hardware ones, governors decide on the current
+//! frequency and statistics advance as virtual time passes.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use cpufreq::{Cpu, SimulatedPolicy, Simulator};
+//!
+//! let simulator = Arc::new(Simulator::new()
+//!     .with_policy(SimulatedPolicy::new(&[0, 1]))
+//!     .with_policy(SimulatedPolicy::new(&[2, 3]).frequencies(&[3000000, 2000000, 1000000])));
+//!
+//! let cpu = Cpu::with_backend(simulator.clone(), 1);
+//! cpu.modify_policy_governor("powersave").unwrap();
+//! simulator.advance(Duration::from_millis(500));
+//!
+//! assert_eq!(Cpu::with_backend(simulator.clone(), 0).get_freq().unwrap(), 800000);
+//! ```
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend};
+use ::cpu_set::CpuSet;
+use ::error::CpuPowerError;
+use ::policy::Policy;
+use ::result::Result;
+use ::stat::Stat;
+use ::types::{CpuId, Frequency};
+
+use std::collections::BTreeMap;
+use std::string::String;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use std::vec::Vec;
+
+
+/// Governors every simulated policy offers unless told otherwise
+const DEFAULT_GOVERNORS: [&str; 5] = ["conservative", "ondemand", "userspace", "powersave", "performance"];
+
+/// Frequency table every simulated policy has unless told otherwise
+const DEFAULT_FREQUENCIES: [Frequency; 3] = [2400000, 1600000, 800000];
+
+/// `time_in_state` is reported in units of 10ms
+const TIME_IN_STATE_UNIT_NS: u64 = 10000000;
+
+
+/// Configuration and state of one simulated cpufreq policy
+#[derive(Debug, Clone)]
+pub struct SimulatedPolicy {
+    cpus: Vec<CpuId>,
+    driver: String,
+    governors: Vec<String>,
+    frequencies: Vec<Frequency>,
+    transition_latency: u64,
+    governor: String,
+    /// Limits as requested, the effective min never exceeds max
+    min: Frequency,
+    max: Frequency,
+    cur: Frequency,
+    setspeed: Frequency,
+    time_in_state_ns: BTreeMap<Frequency, u64>,
+    total_trans: u64
+}
+
+
+impl SimulatedPolicy {
+    /// Policy shared by the given cpus with an acpi-cpufreq like setup
+    pub fn new(cpus: &[CpuId]) -> SimulatedPolicy {
+        let mut policy = SimulatedPolicy {
+            cpus: cpus.to_vec(),
+            driver: String::from("acpi-cpufreq"),
+            governors: DEFAULT_GOVERNORS.iter().map(|governor| String::from(*governor)).collect(),
+            frequencies: vec![],
+            transition_latency: 10000,
+            governor: String::from("ondemand"),
+            min: 0,
+            max: 0,
+            cur: 0,
+            setspeed: 0,
+            time_in_state_ns: BTreeMap::new(),
+            total_trans: 0
+        };
+
+        policy.set_frequencies(&DEFAULT_FREQUENCIES);
+        policy
+    }
+
+    /// Frequency table, listed from the highest frequency like the kernel does
+    pub fn frequencies(mut self, frequencies: &[Frequency]) -> SimulatedPolicy {
+        self.set_frequencies(frequencies);
+        self
+    }
+
+    pub fn governors(mut self, governors: &[&str]) -> SimulatedPolicy {
+        self.governors = governors.iter().map(|governor| String::from(*governor)).collect();
+        if !self.governors.contains(&self.governor) {
+            self.governor = self.governors.first().cloned().unwrap_or_default();
+        }
+        self.update_frequency();
+        self
+    }
+
+    /// Governor the policy starts with
+    pub fn governor(mut self, governor: &str) -> SimulatedPolicy {
+        self.governor = String::from(governor);
+        self.update_frequency();
+        self
+    }
+
+    pub fn driver(mut self, driver: &str) -> SimulatedPolicy {
+        self.driver = String::from(driver);
+        self
+    }
+
+    /// Transition latency in nanoseconds
+    pub fn transition_latency(mut self, latency: u64) -> SimulatedPolicy {
+        self.transition_latency = latency;
+        self
+    }
+
+    fn set_frequencies(&mut self, frequencies: &[Frequency]) {
+        let mut frequencies = frequencies.to_vec();
+        frequencies.sort_by(|a, b| b.cmp(a));
+        frequencies.dedup();
+
+        self.frequencies = frequencies;
+        self.time_in_state_ns = self.frequencies.iter().map(|freq| (*freq, 0)).collect();
+        self.min = self.hardware_min();
+        self.max = self.hardware_max();
+        self.cur = self.max;
+        self.setspeed = self.max;
+        self.update_frequency();
+    }
+
+    fn hardware_min(&self) -> Frequency {
+        self.frequencies.last().cloned().unwrap_or(0)
+    }
+
+    fn hardware_max(&self) -> Frequency {
+        self.frequencies.first().cloned().unwrap_or(0)
+    }
+
+    fn clamp_to_hardware(&self, freq: Frequency) -> Frequency {
+        freq.max(self.hardware_min()).min(self.hardware_max())
+    }
+
+    /// Like the kernel, a min request above max is held down to max
+    fn effective_min(&self) -> Frequency {
+        self.min.min(self.max)
+    }
+
+    /// Lowest table frequency at or above the target within the policy limits
+    fn resolve(&self, target: Frequency) -> Frequency {
+        let target = target.max(self.effective_min()).min(self.max);
+
+        self.frequencies.iter().cloned()
+            .filter(|freq| *freq >= target && *freq <= self.max)
+            .min()
+            .unwrap_or(self.max)
+    }
+
+    /// Let the governor pick the frequency after anything has changed
+    fn update_frequency(&mut self) {
+        let target = match self.governor.as_str() {
+            "performance" => self.max,
+            "powersave" => self.effective_min(),
+            "userspace" => self.setspeed,
+            _ => self.cur
+        };
+
+        let freq = self.resolve(target);
+
+        if freq != self.cur {
+            self.cur = freq;
+            self.total_trans += 1;
+        }
+    }
+
+    fn set_min(&mut self, min: Frequency) {
+        self.min = self.clamp_to_hardware(min);
+        self.update_frequency();
+    }
+
+    fn set_max(&mut self, max: Frequency) {
+        self.max = self.clamp_to_hardware(max);
+        self.update_frequency();
+    }
+
+    fn set_governor(&mut self, governor: &str) -> Result<()> {
+        if !self.governors.iter().any(|available| available == governor) {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        if governor == "userspace" && self.governor != "userspace" {
+            self.setspeed = self.cur;
+        }
+
+        self.governor = String::from(governor);
+        self.update_frequency();
+        Ok(())
+    }
+
+    fn set_setspeed(&mut self, freq: Frequency) -> Result<()> {
+        if self.governor != "userspace" {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.setspeed = freq;
+        self.update_frequency();
+        Ok(())
+    }
+
+    fn advance(&mut self, elapsed_ns: u64) {
+        let time_in_state = self.time_in_state_ns.entry(self.cur).or_insert(0);
+        *time_in_state = time_in_state.saturating_add(elapsed_ns);
+    }
+
+    /// Content of an attribute under `cpuN/cpufreq`, as the kernel formats it
+    fn attribute(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "affected_cpus" | "related_cpus" => backend::format_list(&self.cpus),
+            "cpuinfo_cur_freq" | "scaling_cur_freq" => self.cur.to_string(),
+            "cpuinfo_max_freq" => self.hardware_max().to_string(),
+            "cpuinfo_min_freq" => self.hardware_min().to_string(),
+            "cpuinfo_transition_latency" => self.transition_latency.to_string(),
+            "scaling_available_frequencies" => backend::format_list(&self.frequencies),
+            "scaling_available_governors" => backend::format_list(&self.governors),
+            "scaling_driver" => self.driver.clone(),
+            "scaling_governor" => self.governor.clone(),
+            "scaling_max_freq" => self.max.to_string(),
+            "scaling_min_freq" => self.effective_min().to_string(),
+            "scaling_setspeed" => backend::format_setspeed(&self.governor, self.setspeed),
+            "stats/time_in_state" => backend::format_stats(&self.stats()),
+            "stats/total_trans" => self.total_trans.to_string(),
+            _ => return None
+        };
+
+        Some(value)
+    }
+
+    /// Write an attribute under `cpuN/cpufreq`, read-only ones are refused with EACCES
+    fn set_attribute(&mut self, name: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+
+        match name {
+            "scaling_max_freq" => self.set_max(backend::parse_frequency(value)?),
+            "scaling_min_freq" => self.set_min(backend::parse_frequency(value)?),
+            "scaling_governor" => return self.set_governor(value),
+            "scaling_setspeed" => return self.set_setspeed(backend::parse_frequency(value)?),
+            _ if self.attribute(name).is_some() => {
+                return Err(CpuPowerError::from(errno::Errno(libc::EACCES)));
+            },
+            _ => return Err(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+        }
+
+        Ok(())
+    }
+
+    /// Number of the `cpufreq/policyN` directory, that's its first cpu
+    fn number(&self) -> u32 {
+        self.cpus.iter().cloned().min().unwrap_or(0)
+    }
+
+    fn policy(&self) -> Policy {
+        Policy::new(self.effective_min(), self.max, &self.governor)
+    }
+
+    fn stats(&self) -> Vec<Stat> {
+        self.frequencies.iter().map(|freq| Stat {
+            freq: *freq,
+            time_in_state: self.time_in_state_ns.get(freq).cloned().unwrap_or(0) / TIME_IN_STATE_UNIT_NS
+        }).collect()
+    }
+}
+
+
+#[derive(Debug, Default)]
+pub struct Simulator {
+    policies: Mutex<Vec<SimulatedPolicy>>
+}
+
+
+impl Simulator {
+    /// Simulator without any cpu, add them with `with_policy`
+    pub fn new() -> Simulator {
+        Simulator::default()
+    }
+
+    /// Simulator with `count` cpus each having its own default policy
+    pub fn with_cpus(count: CpuId) -> Simulator {
+        (0..count).fold(Simulator::new(), |simulator, id| simulator.with_policy(SimulatedPolicy::new(&[id])))
+    }
+
+    /// Add a policy, its cpus are taken away from the existing policies
+    pub fn with_policy(self, policy: SimulatedPolicy) -> Simulator {
+        {
+            let mut policies = self.lock();
+
+            for existing in policies.iter_mut() {
+                existing.cpus.retain(|cpu| !policy.cpus.contains(cpu));
+            }
+
+            policies.retain(|existing| !existing.cpus.is_empty());
+            policies.push(policy);
+        }
+
+        self
+    }
+
+    /// Let virtual time pass, accounting it to the current frequency of every policy.
+    ///
+    /// Time is kept in nanoseconds, so many short steps add up like a long one.
+    pub fn advance(&self, elapsed: Duration) {
+        let elapsed_ns = elapsed.as_secs().saturating_mul(1000000000)
+            .saturating_add(u64::from(elapsed.subsec_nanos()));
+
+        for policy in self.lock().iter_mut() {
+            policy.advance(elapsed_ns);
+        }
+    }
+
+    /// Write `scaling_setspeed` of the policy the cpu belongs to.
+    ///
+    /// Just like the kernel it is rejected unless the userspace governor is active.
+    pub fn set_setspeed(&self, id: CpuId, freq: Frequency) -> Result<()> {
+        self.write(id, |policy| policy.set_setspeed(freq))
+    }
+
+    /// Current value of `scaling_setspeed`, `None` if the governor isn't userspace
+    pub fn get_setspeed(&self, id: CpuId) -> Result<Option<Frequency>> {
+        self.read(id, |policy| if policy.governor == "userspace" { Some(policy.setspeed) } else { None })
+    }
+
+    /// Cpu whose policy holds the attribute and the attribute name, for
+    /// both `cpuN/cpufreq/...` and `cpufreq/policyN/...` paths
+    fn locate<'a>(&self, path: &'a str) -> Option<(CpuId, &'a str)> {
+        if let Some(attribute) = backend::parse_cpufreq_attribute(path) {
+            return Some(attribute);
+        }
+
+        let (number, name) = backend::parse_policy_attribute(path)?;
+
+        if self.read(number, SimulatedPolicy::number).ok()? == number {
+            Some((number, name))
+        } else {
+            None
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<SimulatedPolicy>> {
+        self.policies.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read<T, F: FnOnce(&SimulatedPolicy) -> T>(&self, id: CpuId, f: F) -> Result<T> {
+        self.lock().iter()
+            .find(|policy| policy.cpus.contains(&id))
+            .map(f)
+            .ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+
+    fn write<T, F: FnOnce(&mut SimulatedPolicy) -> Result<T>>(&self, id: CpuId, f: F) -> Result<T> {
+        match self.lock().iter_mut().find(|policy| policy.cpus.contains(&id)) {
+            Some(policy) => f(policy),
+            None => Err(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+        }
+    }
+}
+
+
+impl Backend for Simulator {
+    fn read_attribute(&self, path: &str) -> Result<String> {
+        let enoent = CpuPowerError::from(errno::Errno(libc::ENOENT));
+
+        match self.locate(path) {
+            Some((id, name)) => self.read(id, |policy| policy.attribute(name))?.ok_or(enoent),
+            None => Err(enoent)
+        }
+    }
+
+    fn write_attribute(&self, path: &str, value: &str) -> Result<()> {
+        match self.locate(path) {
+            Some((id, name)) => self.write(id, |policy| policy.set_attribute(name, value)),
+            None => Err(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+        }
+    }
+
+    fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+        let mut paths: Vec<String> = Vec::new();
+
+        for policy in self.lock().iter() {
+            let mut directories: Vec<String> = policy.cpus.iter().map(|id| backend::cpufreq_directory(*id)).collect();
+            directories.push(backend::policy_directory(policy.number()));
+
+            for directory in directories {
+                paths.extend(backend::CPUFREQ_ATTRIBUTE_NAMES.iter().map(|name| format!("{}/{}", directory, name)));
+            }
+        }
+
+        backend::list_children(&paths, path)
+    }
+
+    fn cpu_exists(&self, id: CpuId) -> bool {
+        self.read(id, |_| ()).is_ok()
+    }
+
+    fn get_freq_kernel(&self, id: CpuId) -> Result<Frequency> {
+        self.read(id, |policy| policy.cur)
+    }
+
+    fn get_freq_hardware(&self, id: CpuId) -> Result<Frequency> {
+        self.read(id, |policy| policy.cur)
+    }
+
+    /// Same as libcpupower: switch to userspace governor and write setspeed
+    fn set_frequency(&self, id: CpuId, freq: Frequency) -> Result<()> {
+        self.write(id, |policy| {
+            if policy.governor != "userspace" {
+                policy.set_governor("userspace")?;
+            }
+            policy.set_setspeed(freq)
+        })
+    }
+
+    fn get_transition_latency(&self, id: CpuId) -> Result<u64> {
+        self.read(id, |policy| policy.transition_latency)
+    }
+
+    fn get_hardware_limits(&self, id: CpuId) -> Result<(Frequency, Frequency)> {
+        self.read(id, |policy| (policy.hardware_min(), policy.hardware_max()))
+    }
+
+    fn get_driver(&self, id: CpuId) -> Result<String> {
+        self.read(id, |policy| policy.driver.clone())
+    }
+
+    fn get_policy(&self, id: CpuId) -> Result<Policy> {
+        self.read(id, SimulatedPolicy::policy)
+    }
+
+    /// Nothing changes when the limits are inverted or the governor isn't offered
+    fn set_policy(&self, id: CpuId, new_policy: &Policy) -> Result<()> {
+        if new_policy.max < new_policy.min {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.write(id, |policy| {
+            if !policy.governors.contains(&new_policy.governor) {
+                return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+            }
+
+            policy.set_max(new_policy.max);
+            policy.set_min(new_policy.min);
+            policy.set_governor(&new_policy.governor)
+        })
+    }
+
+    fn modify_policy_min(&self, id: CpuId, min: Frequency) -> Result<()> {
+        self.write(id, |policy| {
+            policy.set_min(min);
+            Ok(())
+        })
+    }
+
+    fn modify_policy_max(&self, id: CpuId, max: Frequency) -> Result<()> {
+        self.write(id, |policy| {
+            policy.set_max(max);
+            Ok(())
+        })
+    }
+
+    fn modify_policy_governor(&self, id: CpuId, governor: &str) -> Result<()> {
+        self.write(id, |policy| policy.set_governor(governor))
+    }
+
+    fn get_available_governors(&self, id: CpuId) -> Result<Vec<String>> {
+        self.read(id, |policy| policy.governors.clone())
+    }
+
+    fn get_available_frequencies(&self, id: CpuId) -> Result<Vec<Frequency>> {
+        self.read(id, |policy| policy.frequencies.clone())
+    }
+
+    fn get_affected_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        self.read(id, |policy| policy.cpus.iter().cloned().collect())
+    }
+
+    fn get_related_cpus(&self, id: CpuId) -> Result<CpuSet> {
+        self.read(id, |policy| policy.cpus.iter().cloned().collect())
+    }
+
+    fn get_stats(&self, id: CpuId) -> Result<Vec<Stat>> {
+        self.read(id, SimulatedPolicy::stats)
+    }
+
+    fn get_transitions(&self, id: CpuId) -> Result<u64> {
+        self.read(id, |policy| policy.total_trans)
+    }
+}
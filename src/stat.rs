@@ -1,7 +1,7 @@
 use ::types::Frequency;
 use std::fmt;
 
-
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Stat {
     pub freq: Frequency,
     pub time_in_state: u64
@@ -0,0 +1,105 @@
+//! # Native access to the cpufreq sysfs interface
+//!
+//! Pure Rust replacement of the `sysfs_*` functions from `base/sysfs.c`:
+//! attributes are read from (or written to) files under the root
+//! directory, so no C library is needed at all.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::Backend;
+use ::error::CpuPowerError;
+use ::result::Result;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::vec::Vec;
+
+
+/// Default location of the cpu devices in sysfs
+pub const PATH_TO_CPU: &str = "/sys/devices/system/cpu";
+
+
+/// # Location of the cpu devices in sysfs
+///
+/// Defaults to `/sys/devices/system/cpu`. Point it to any other directory
+/// with the same layout (e.g. a dump taken from another machine) and create
+/// Cpu's against it with `Cpu::new_in` or `Cpu::get_all_in`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysfsRoot {
+    path: PathBuf
+}
+
+
+impl Default for SysfsRoot {
+    fn default() -> SysfsRoot {
+        SysfsRoot::new(PATH_TO_CPU)
+    }
+}
+
+
+impl SysfsRoot {
+    pub fn new<P: Into<PathBuf>>(path: P) -> SysfsRoot {
+        SysfsRoot {
+            path: path.into()
+        }
+    }
+
+    /// Directory holding the cpuN subdirectories
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+
+impl Backend for SysfsRoot {
+    fn read_attribute(&self, path: &str) -> Result<String> {
+        read_file(&self.path.join(path))
+    }
+
+    fn write_attribute(&self, path: &str, value: &str) -> Result<()> {
+        write_file(&self.path.join(path), value)
+    }
+
+    fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+
+        for entry in fs::read_dir(self.path.join(path))? {
+            result.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+
+        result.sort();
+        Ok(result)
+    }
+}
+
+
+/// Read the whole attribute stripping the trailing newline
+pub fn read_file(path: &Path) -> Result<String> {
+    let mut content = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut content)?;
+
+    let mut content = String::from_utf8(content)?;
+    let trimmed_len = content.trim_end().len();
+    content.truncate(trimmed_len);
+
+    Ok(content)
+}
+
+
+pub fn write_file(path: &Path, value: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+
+    let written = file.write(value.as_bytes())?;
+
+    if written != value.len() {
+        return Err(CpuPowerError::from(errno::Errno(libc::ENODEV)));
+    }
+
+    Ok(())
+}
@@ -1,31 +1,358 @@
-#[cfg(test)]
-
 extern crate libc;
 extern crate errno;
 
-use std::iter::Iterator;
-use ::cpu::Cpu;
+use std::env;
+use std::fs;
+use std::os;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ::backend::{Backend, default_backend};
+use ::cpu::{Cpu, Iterator};
+use ::cpu_set::CpuSet;
+use ::simulator::Simulator;
+use ::sysfs::{PATH_TO_CPU, SysfsRoot};
+use ::tunables::TunablesScope;
+
+
+/// Whether the cpus of this machine can be tested: the mock library is
+/// linked or sysfs has cpufreq
+fn has_cpufreq() -> bool {
+    cfg!(cpufreq = "mock") || Path::new(PATH_TO_CPU).join("cpu0/cpufreq").is_dir()
+}
+
+
+/// Cpus the hardware tests run on: those of this machine when it has
+/// cpufreq, a `Simulator` of two cpus otherwise
+pub struct TestSystem {
+    fake: Option<Arc<Simulator>>
+}
+
+impl TestSystem {
+    pub fn new() -> TestSystem {
+        TestSystem {
+            fake: if has_cpufreq() { None } else { Some(Arc::new(Simulator::with_cpus(2))) }
+        }
+    }
+
+    /// Whether settings can be changed: as root on this machine, always
+    /// on a simulated one
+    pub fn is_privileged(&self) -> bool {
+        self.fake.is_some() || unsafe { libc::geteuid() == 0 }
+    }
+
+    pub fn backend(&self) -> Arc<dyn Backend> {
+        match self.fake {
+            Some(ref fake) => fake.clone(),
+            None => default_backend()
+        }
+    }
+
+    pub fn cpu(&self, id: u32) -> Cpu {
+        Cpu::with_backend(self.backend(), id)
+    }
+
+    pub fn get_all(&self) -> Iterator {
+        Cpu::get_all_with(self.backend())
+    }
+
+    pub fn exists(&self, id: u32) -> bool {
+        self.backend().cpu_exists(id)
+    }
+
+    /// Present cpus, read from sysfs rather than through the backend
+    pub fn present(&self) -> CpuSet {
+        match self.fake {
+            Some(_) => (0..2).collect(),
+            None => CpuSet::from_cpu_list(&fs::read_to_string(Path::new(PATH_TO_CPU).join("present")).unwrap()).unwrap()
+        }
+    }
+
+    pub fn get_max_cpu(&self) -> usize {
+        if cfg!(cpufreq = "mock") {
+            1
+        } else {
+            // Dirty hack
+            self.get_all().count() - 1
+        }
+    }
+}
+
+
+static FAKE_SYSFS_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Temporary directory laid out like /sys/devices/system/cpu
+pub struct FakeSysfs {
+    path: PathBuf
+}
+
+impl FakeSysfs {
+    pub fn new() -> FakeSysfs {
+        let path = env::temp_dir().join(format!(
+            "cpufreq-test-{}-{}", process::id(), FAKE_SYSFS_COUNTER.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir_all(&path).unwrap();
+        FakeSysfs { path }
+    }
+
+    /// Populate cpuN with a typical acpi-cpufreq setup
+    pub fn with_cpu(self, id: u32) -> FakeSysfs {
+        self.set(id, "scaling_cur_freq", "2400000\n")
+            .set(id, "cpuinfo_cur_freq", "2400000\n")
+            .set(id, "cpuinfo_min_freq", "800000\n")
+            .set(id, "cpuinfo_max_freq", "2400000\n")
+            .set(id, "cpuinfo_transition_latency", "10000\n")
+            .set(id, "scaling_min_freq", "800000\n")
+            .set(id, "scaling_max_freq", "2400000\n")
+            .set(id, "scaling_governor", "ondemand\n")
+            .set(id, "scaling_driver", "acpi-cpufreq\n")
+            .set(id, "scaling_setspeed", "<unsupported>\n")
+            .set(id, "scaling_available_governors", "conservative ondemand userspace powersave performance \n")
+            .set(id, "scaling_available_frequencies", "2400000 1600000 800000 \n")
+            .set(id, "affected_cpus", &format!("{}\n", id))
+            .set(id, "related_cpus", &format!("{}\n", id))
+            .set(id, "stats/time_in_state", "2400000 1000\n1600000 200\n800000 30\n")
+            .set(id, "stats/total_trans", "42\n")
+    }
+
+    /// Populate cpufreq/policyN shared by the cpus, their cpufreq directory
+    /// being a link to it like in sysfs
+    pub fn with_policy(self, policy: u32, cpus: &[u32]) -> FakeSysfs {
+        fs::create_dir_all(self.path.join(format!("cpufreq/policy{}", policy))).unwrap();
+
+        for cpu in cpus {
+            fs::create_dir_all(self.path.join(format!("cpu{}", cpu))).unwrap();
+            os::unix::fs::symlink(format!("../cpufreq/policy{}", policy),
+                                  self.path.join(format!("cpu{}/cpufreq", cpu))).unwrap();
+        }
+
+        let members = format!("{}\n", cpus.iter().map(|cpu| cpu.to_string()).collect::<Vec<String>>().join(" "));
+        self.with_cpu(policy)
+            .set(policy, "affected_cpus", &members)
+            .set(policy, "related_cpus", &members)
+    }
+
+    /// Write a cpufreq attribute of cpuN, creating directories on the way
+    pub fn set(self, id: u32, attribute: &str, value: &str) -> FakeSysfs {
+        let path = self.path.join(format!("cpu{}/cpufreq", id)).join(attribute);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, value).unwrap();
+        self
+    }
+
+    /// Write any file, `path` is relative to the root
+    pub fn with_file(self, path: &str, value: &str) -> FakeSysfs {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, value).unwrap();
+        self
+    }
+
+    pub fn get(&self, id: u32, attribute: &str) -> String {
+        fs::read_to_string(self.path.join(format!("cpu{}/cpufreq", id)).join(attribute)).unwrap()
+    }
+
+    pub fn root(&self) -> SysfsRoot {
+        SysfsRoot::new(self.path.clone())
+    }
+}
+
+impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+
+/// Machine the feature tests start from: policy0 with cpus 0-1 and
+/// policy2 with cpus 2-3 running acpi-cpufreq, every cpu but cpu0 being
+/// hotpluggable. Tests override what their feature needs and `build` a
+/// `FakeSysfs` of it.
+pub struct Machine {
+    policies: Vec<(u32, Vec<u32>)>,
+    offline: Vec<u32>,
+    /// Cpufreq attributes of one policy, or of every one for `None`
+    attributes: Vec<(Option<u32>, String, String)>,
+    files: Vec<(String, String)>
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            policies: vec![(0, vec![0, 1]), (2, vec![2, 3])],
+            offline: Vec::new(),
+            attributes: Vec::new(),
+            files: Vec::new()
+        }
+    }
+
+    /// Replace the policies, given with their cpus
+    pub fn with_policies(mut self, policies: &[(u32, &[u32])]) -> Machine {
+        self.policies = policies.iter().map(|&(policy, cpus)| (policy, cpus.to_vec())).collect();
+        self
+    }
+
+    /// Replace the policies by one per cpu
+    pub fn with_cpus(mut self, cpus: &[u32]) -> Machine {
+        self.policies = cpus.iter().map(|&cpu| (cpu, vec![cpu])).collect();
+        self
+    }
+
+    /// Add an offline cpu, which has no cpufreq directory
+    pub fn with_offline(mut self, id: u32) -> Machine {
+        self.offline.push(id);
+        self
+    }
+
+    /// Write a cpufreq attribute of the policy
+    pub fn set(mut self, policy: u32, attribute: &str, value: &str) -> Machine {
+        self.attributes.push((Some(policy), String::from(attribute), String::from(value)));
+        self
+    }
+
+    /// Write a cpufreq attribute of every policy
+    pub fn set_all(mut self, attribute: &str, value: &str) -> Machine {
+        self.attributes.push((None, String::from(attribute), String::from(value)));
+        self
+    }
+
+    /// Write any file, `path` is relative to the root
+    pub fn with_file(mut self, path: &str, value: &str) -> Machine {
+        self.files.push((String::from(path), String::from(value)));
+        self
+    }
+
+    /// `cpuN/topology`, `siblings` being the hardware threads of the core
+    pub fn with_topology(self, id: u32, package: u32, core: u32, siblings: &str) -> Machine {
+        let directory = format!("cpu{}/topology", id);
+        self.with_file(&format!("{}/physical_package_id", directory), &format!("{}\n", package))
+            .with_file(&format!("{}/die_id", directory), "0\n")
+            .with_file(&format!("{}/cluster_id", directory), "-1\n")
+            .with_file(&format!("{}/core_id", directory), &format!("{}\n", core))
+            .with_file(&format!("{}/thread_siblings_list", directory), &format!("{}\n", siblings))
+            .with_file(&format!("{}/core_cpus_list", directory), &format!("{}\n", siblings))
+    }
+
+    /// Two packages of two cores with two threads each, cpus 4-7 being
+    /// the second threads of cpus 0-3. Written for the cpus of the
+    /// policies, offline cpus have no topology.
+    pub fn with_smt(self) -> Machine {
+        let cpus: Vec<u32> = self.policies.iter().flat_map(|policy| policy.1.clone()).collect();
+
+        cpus.into_iter().fold(self, |machine, id| {
+            let (package, core) = ((id % 4) / 2, id % 2);
+            let first = package * 2 + core;
+            machine.with_topology(id, package, core, &format!("{},{}", first, first + 4))
+        })
+    }
+
+    /// `cpuN/cpu_capacity` of big.LITTLE and hybrid systems
+    pub fn with_capacity(self, id: u32, capacity: u64) -> Machine {
+        self.with_file(&format!("cpu{}/cpu_capacity", id), &format!("{}\n", capacity))
+    }
+
+    /// big.LITTLE: four little cpus sharing policy0 and two big ones
+    /// sharing policy4
+    pub fn with_big_little(self) -> Machine {
+        (0..6).fold(self.with_policies(&[(0, &[0, 1, 2, 3]), (4, &[4, 5])]), |machine, id| {
+            machine.with_capacity(id, if id < 4 { 446 } else { 1024 })
+        })
+        .set(0, "cpuinfo_min_freq", "500000\n")
+        .set(0, "cpuinfo_max_freq", "1800000\n")
+    }
+
+    /// Global knobs of `intel_pstate`, the policies keeping their driver
+    pub fn with_intel_pstate(self) -> Machine {
+        self.with_file("intel_pstate/status", "active\n")
+            .with_file("intel_pstate/min_perf_pct", "22\n")
+            .with_file("intel_pstate/max_perf_pct", "100\n")
+            .with_file("intel_pstate/no_turbo", "0\n")
+            .with_file("intel_pstate/hwp_dynamic_boost", "0\n")
+            .with_file("intel_pstate/num_pstates", "39\n")
+            .with_file("intel_pstate/turbo_pct", "33\n")
+    }
+
+    /// `amd-pstate` in active mode driving every policy
+    pub fn with_amd_pstate(self) -> Machine {
+        self.set_all("amd_pstate_highest_perf", "166\n")
+            .set_all("amd_pstate_max_freq", "4350000\n")
+            .set_all("amd_pstate_lowest_nonlinear_freq", "1400000\n")
+            .set_all("amd_pstate_prefcore_ranking", "236\n")
+            .set_all("amd_pstate_hw_prefcore", "enabled\n")
+            .with_file("amd_pstate/status", "active\n")
+            .with_file("amd_pstate/prefcore", "enabled\n")
+    }
+
+    /// `intel_pstate` with HWP driving every policy
+    pub fn with_hwp(self) -> Machine {
+        self.set_all("scaling_driver", "intel_pstate\n")
+            .set_all("energy_performance_preference", "balance_performance\n")
+            .set_all("energy_performance_available_preferences",
+                     "default performance balance_performance balance_power power \n")
+    }
+
+    pub fn with_energy_perf_bias(self, id: u32, bias: u8) -> Machine {
+        self.with_file(&format!("cpu{}/power/energy_perf_bias", id), &format!("{}\n", bias))
+    }
+
+    /// Put the governor in charge with its tunables, global ones applying
+    /// to every policy
+    pub fn with_tunables(self, governor: &str, scope: TunablesScope) -> Machine {
+        let (machine, directory) = match scope {
+            TunablesScope::Global => (self.set_all("scaling_governor", &format!("{}\n", governor)), format!("cpufreq/{}", governor)),
+            TunablesScope::PerPolicy(policy) => (self.set(policy, "scaling_governor", &format!("{}\n", governor)),
+                                                 format!("cpufreq/policy{}/{}", policy, governor))
+        };
+        let tunables: &[(&str, &str)] = match governor {
+            "ondemand" => &[("sampling_rate", "10000"), ("up_threshold", "95"), ("sampling_down_factor", "1"),
+                            ("ignore_nice_load", "0"), ("powersave_bias", "0")],
+            "conservative" => &[("sampling_rate", "20000"), ("up_threshold", "80"), ("down_threshold", "20"),
+                                ("freq_step", "5")],
+            "schedutil" => &[("rate_limit_us", "1000")],
+            _ => &[]
+        };
+
+        tunables.iter().fold(machine, |machine, &(name, value)| {
+            machine.with_file(&format!("{}/{}", directory, name), &format!("{}\n", value))
+        })
+    }
+
+    pub fn build(self) -> FakeSysfs {
+        let mut sysfs = FakeSysfs::new();
 
-/// Get policy test case
+        for &(policy, ref cpus) in &self.policies {
+            sysfs = sysfs.with_policy(policy, cpus);
 
-fn get_max_cpu() -> usize {
-    if cfg!(cpufreq = "mock") {
-        1
-    } else {
-        // Dirty hack
-        Cpu::get_all().count() - 1
+            for (_, attribute, value) in self.attributes.iter().filter(|set| set.0.is_none_or(|id| id == policy)) {
+                sysfs = sysfs.set(policy, attribute, value);
+            }
+            for &cpu in cpus.iter().filter(|&&cpu| cpu != 0) {
+                sysfs = sysfs.with_file(&format!("cpu{}/online", cpu), "1\n");
+            }
+        }
+        for id in &self.offline {
+            sysfs = sysfs.with_file(&format!("cpu{}/online", id), "0\n");
+        }
+        for (path, value) in &self.files {
+            sysfs = sysfs.with_file(path, value);
+        }
+
+        sysfs
     }
 }
 
+
 mod policy {
     extern crate libc;
     extern crate errno;
-    use ::cpu::Cpu;
     use ::policy::Policy;
+    use super::TestSystem;
 
     #[test]
     fn get_policy_can_return_real_policy_on_normal_operation() {
-        let cpu = Cpu::new(0);
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
         cpu.get_policy().unwrap();
     }
 
@@ -39,198 +366,2264 @@ mod policy {
 
     #[test]
     fn set_policy_does_really_set_policy() {
-        let cpu = Cpu::new(0);
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+
+        if system.is_privileged() {
+            cpu.set_policy(&Policy::new(0, 1000000, "powersave")).unwrap();
+        } else {
+            match *cpu.set_policy(&Policy::new(0, 1000000, "powersave")).unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
+            };
+        }
+
+
+    }
+}
+
+mod cpu {
+    extern crate libc;
+    extern crate errno;
+    use super::TestSystem;
+
+    #[test]
+    fn get_all_returns_all_existing_cpus() {
+        let system = TestSystem::new();
+        let ids: Vec<u32> = system.get_all().map(|cpu| cpu.get_id()).collect();
+
+        assert_eq!(ids, system.present().into_iter().collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn exists_returns_true_if_cpu_exists() {
+        assert!(TestSystem::new().exists(0));
+    }
+
+    #[test]
+    fn exists_returns_false_if_cpu_doesnt_exist() {
+        let system = TestSystem::new();
+        assert!(!system.exists(system.get_max_cpu() as u32 + 1));
+    }
+
+    #[test]
+    fn get_freq_kernel_returns_frequency() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+        cpu.get_freq_kernel()
+            .map(|freq| assert!(freq > 0))
+            .unwrap();
+    }
 
-        let euid: libc::uid_t;
+    #[test]
+    fn get_freq_hardware_returns_frequency_if_root() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
 
-        unsafe {
-            euid = libc::geteuid();
+        if system.is_privileged() {
+            cpu.get_freq_hardware()
+                .map(|freq| assert!(freq > 0))
+                .unwrap();
+        } else {
+            match *cpu.get_freq_hardware().unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
+            };
         }
+    }
 
-        if euid == 0 {
-            cpu.set_policy(&Policy::new(0, 1000000, "powersave")).unwrap();
+    #[test]
+    fn get_freq_returns_frequency() {
+        TestSystem::new().get_all()
+            .next().unwrap()
+            .get_freq()
+            .map(|freq| assert!(freq > 0)).unwrap();
+    }
+
+    #[test]
+    fn set_freq_sets_frequency_if_root() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+
+        if system.is_privileged() {
+            let min = cpu.get_policy().unwrap().min;
+            let max = cpu.get_policy().unwrap().max;
+            assert!(0 < min);
+            assert!(0 < max);
+            cpu.set_freq(min).unwrap();
+            assert_eq!(cpu.get_freq().unwrap(), min);
+            cpu.set_freq(max).unwrap();
+            assert_eq!(cpu.get_freq().unwrap(), max);
         } else {
-            match cpu.set_policy(&Policy::new(0, 1000000, "powersave")).unwrap_err() {
-                ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
-                error => panic!("Wrong error appeared: {}", error)
+            match *cpu.set_freq(100000).unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
             };
         }
+    }
+
+    #[test]
+    fn get_transition_latency_returns_latency() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+        assert!(0 < cpu.get_transition_latency().unwrap());
+    }
 
 
+    #[test]
+    fn modify_policy_max_can_modify_if_root() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+
+        let (min, max) = cpu.get_hardware_limits().unwrap();
+        assert!(0 < min);
+        assert!(0 < max);
+
+        if system.is_privileged() {
+            cpu.modify_policy_max(min).unwrap();
+            assert_eq!(cpu.get_policy().unwrap().max, min);
+            cpu.modify_policy_max(max).unwrap();
+            assert_eq!(cpu.get_policy().unwrap().max, max);
+        } else {
+            match *cpu.modify_policy_max(min).unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
+            };
+        }
     }
-}
 
-#[test]
-fn get_all_returns_all_existing_cpus() {
-    assert_eq!(Cpu::get_all().count(), get_max_cpu() + 1);
-}
+    #[test]
+    fn modify_policy_min_can_modify_if_root() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
 
-#[test]
-fn exists_returns_true_if_cpu_exists() {
-    assert!(Cpu::exists(0));
-}
+        let (min, max) = cpu.get_hardware_limits().unwrap();
+        assert!(0 < min);
+        assert!(0 < max);
 
-#[test]
-fn exists_returns_false_if_cpu_doesnt_exist() {
-    assert!(!Cpu::exists(get_max_cpu() as u32 + 1));
-}
+        if system.is_privileged() {
+            cpu.modify_policy_min(min).unwrap();
+            assert_eq!(cpu.get_policy().unwrap().min, min);
+            cpu.modify_policy_min(max).unwrap();
+            assert_eq!(cpu.get_policy().unwrap().min, max);
+        } else {
+            match *cpu.modify_policy_min(min).unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
+            };
+        }
+    }
+
+    #[test]
+    fn modify_policy_governor_can_modify_if_root() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
+
+        let governors = cpu.get_available_governors().unwrap();
+
+        if system.is_privileged() {
+            for governor in governors {
+                cpu.modify_policy_governor(governor.as_ref()).unwrap();
+                assert_eq!(cpu.get_policy().unwrap().governor, governor);
+            }
+        } else {
+            match *cpu.modify_policy_governor(governors.first().unwrap().as_ref()).unwrap_err().kind() {
+                ::error::CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+                ref error => panic!("Wrong error appeared: {}", error)
+            };
+        }
+    }
+
+    #[test]
+    fn get_hardware_limit_returns_limits() {
+        let system = TestSystem::new();
+        let cpu = system.cpu(0);
 
-#[test]
-fn get_freq_kernel_returns_frequency() {
-    let cpu = Cpu::new(0);
-    cpu.get_freq_kernel()
-        .map(|freq| assert!(freq > 0))
-        .unwrap();
+        cpu.get_hardware_limits()
+            .map(|limits: (u64, u64)| assert!(limits.0 > 0 && limits.1 > limits.0) )
+            .unwrap();
+    }
 }
 
-#[test]
-fn get_freq_hardware_returns_frequency_if_root() {
-    let cpu = Cpu::new(0);
+mod sysfs {
+    extern crate errno;
+    use super::FakeSysfs;
+    use ::backend::Backend;
+    use ::error::CpuPowerError;
+    use ::policy::Policy;
 
-    let euid: libc::uid_t;
+    #[test]
+    fn cpu_exists_checks_cpu_directory() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        assert!(sysfs.root().cpu_exists(0));
+        assert!(!sysfs.root().cpu_exists(1));
+    }
 
-    unsafe {
-        euid = libc::geteuid();
+    #[test]
+    fn get_freq_reads_scaling_and_cpuinfo_values() {
+        let sysfs = FakeSysfs::new().with_cpu(0)
+            .set(0, "scaling_cur_freq", "1600000\n");
+        assert_eq!(sysfs.root().get_freq_kernel(0).unwrap(), 1600000);
+        assert_eq!(sysfs.root().get_freq_hardware(0).unwrap(), 2400000);
     }
 
-    if euid == 0 {
-        cpu.get_freq_hardware()
-            .map(|freq| assert!(freq > 0))
-            .unwrap();
-    } else {
-        match cpu.get_freq_hardware().unwrap_err() {
-            ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
+    #[test]
+    fn missing_attribute_is_reported_as_not_supported() {
+        let sysfs = FakeSysfs::new();
+        match sysfs.root().get_freq_kernel(0).unwrap_err() {
+            CpuPowerError::NotSupported{errno: errno::Errno(2)} => (),
             error => panic!("Wrong error appeared: {}", error)
         };
     }
-}
 
-#[test]
-fn get_freq_returns_frequency() {
-    Cpu::get_all()
-        .nth(0).unwrap()
-        .get_freq()
-        .map(|freq| assert!(freq > 0)).unwrap();
-}
+    #[test]
+    fn get_hardware_limits_returns_limits() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        assert_eq!(sysfs.root().get_hardware_limits(0).unwrap(), (800000, 2400000));
+        assert_eq!(sysfs.root().get_transition_latency(0).unwrap(), 10000);
+    }
+
+    #[test]
+    fn get_policy_and_driver_strip_newlines() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        let policy = sysfs.root().get_policy(0).unwrap();
+        assert_eq!((policy.min, policy.max, policy.governor.as_str()), (800000, 2400000, "ondemand"));
+        assert_eq!(sysfs.root().get_driver(0).unwrap(), "acpi-cpufreq");
+    }
+
+    #[test]
+    fn lists_are_parsed() {
+        let sysfs = FakeSysfs::new().with_cpu(0)
+            .set(0, "related_cpus", "0 1 2 3\n");
+        let root = sysfs.root();
+        assert_eq!(root.get_available_governors(0).unwrap(),
+                   vec!["conservative", "ondemand", "userspace", "powersave", "performance"]);
+        assert_eq!(root.get_available_frequencies(0).unwrap(), vec![2400000, 1600000, 800000]);
+        assert_eq!(root.get_affected_cpus(0).unwrap().to_string(), "0");
+        assert_eq!(root.get_related_cpus(0).unwrap().to_string(), "0-3");
+    }
+
+    #[test]
+    fn get_stats_parses_time_in_state() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        let stats = sysfs.root().get_stats(0).unwrap();
+        let stats: Vec<(u64, u64)> = stats.iter().map(|stat| (stat.freq, stat.time_in_state)).collect();
+        assert_eq!(stats, vec![(2400000, 1000), (1600000, 200), (800000, 30)]);
+        assert_eq!(sysfs.root().get_transitions(0).unwrap(), 42);
+    }
 
-#[test]
-fn set_freq_sets_frequency_if_root() {
-    let cpu = Cpu::new(0);
+    #[test]
+    fn modify_policy_writes_attributes() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        let root = sysfs.root();
+        root.modify_policy_min(0, 1600000).unwrap();
+        root.modify_policy_max(0, 1600000).unwrap();
+        root.modify_policy_governor(0, "powersave").unwrap();
+        assert_eq!(sysfs.get(0, "scaling_min_freq"), "1600000");
+        assert_eq!(sysfs.get(0, "scaling_max_freq"), "1600000");
+        assert_eq!(sysfs.get(0, "scaling_governor"), "powersave");
+    }
 
-    let euid: libc::uid_t;
+    #[test]
+    fn modify_policy_governor_rejects_invalid_names() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        match sysfs.root().modify_policy_governor(0, "power save").unwrap_err() {
+            CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(sysfs.get(0, "scaling_governor"), "ondemand\n");
+    }
 
-    unsafe {
-        euid = libc::geteuid();
+    #[test]
+    fn set_policy_writes_all_values() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        sysfs.root().set_policy(0, &Policy::new(1600000, 2400000, "performance")).unwrap();
+        let policy = sysfs.root().get_policy(0).unwrap();
+        assert_eq!((policy.min, policy.max, policy.governor.as_str()), (1600000, 2400000, "performance"));
     }
 
-    if euid == 0 {
-        let min = cpu.get_policy().unwrap().min;
-        let max = cpu.get_policy().unwrap().max;
-        assert!(0 < min);
-        assert!(0 < max);
-        cpu.set_freq(min).unwrap();
-        assert_eq!(cpu.get_freq().unwrap(), min);
-        cpu.set_freq(max).unwrap();
-        assert_eq!(cpu.get_freq().unwrap(), max);
-    } else {
-        match cpu.set_freq(100000).unwrap_err() {
-            ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
+    #[test]
+    fn set_policy_rejects_max_below_min() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        match sysfs.root().set_policy(0, &Policy::new(2400000, 800000, "performance")).unwrap_err() {
+            CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
             error => panic!("Wrong error appeared: {}", error)
         };
     }
-}
 
-#[test]
-fn get_transition_latency_returns_latency() {
-    let cpu = Cpu::new(0);
-    assert!(0 < cpu.get_transition_latency().unwrap());
+    #[test]
+    fn set_frequency_switches_to_userspace_governor() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        sysfs.root().set_frequency(0, 1600000).unwrap();
+        assert_eq!(sysfs.get(0, "scaling_governor"), "userspace");
+        assert_eq!(sysfs.get(0, "scaling_setspeed"), "1600000");
+    }
 }
 
+mod sysfs_root {
+    extern crate errno;
+    extern crate libc;
+    use super::FakeSysfs;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::policy::Policy;
+    use ::sysfs::SysfsRoot;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
 
-#[test]
-fn modify_policy_max_can_modify_if_root() {
-    let cpu = Cpu::new(0);
+    #[test]
+    fn default_root_points_to_sysfs() {
+        assert_eq!(SysfsRoot::default().path(), Path::new("/sys/devices/system/cpu"));
+    }
 
-    let euid: libc::uid_t;
+    #[test]
+    fn get_all_in_returns_cpus_of_the_tree() {
+        let sysfs = FakeSysfs::new().with_cpu(0).with_cpu(1).with_cpu(2);
+        let ids: Vec<u32> = Cpu::get_all_in(&sysfs.root()).map(|cpu| cpu.get_id()).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+    }
 
-    unsafe {
-        euid = libc::geteuid();
+    #[test]
+    fn exists_in_checks_the_tree() {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        assert!(Cpu::exists_in(&sysfs.root(), 0));
+        assert!(!Cpu::exists_in(&sysfs.root(), 1));
     }
 
-    let (min, max) = cpu.get_hardware_limits().unwrap();
-    assert!(0 < min);
-    assert!(0 < max);
+    #[test]
+    fn new_in_reads_and_writes_the_tree() {
+        let sysfs = FakeSysfs::new().with_cpu(0)
+            .set(0, "scaling_cur_freq", "1600000\n");
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
 
-    if euid == 0 {
-        cpu.modify_policy_max(min).unwrap();
-        assert_eq!(cpu.get_policy().unwrap().max, min);
-        cpu.modify_policy_max(max).unwrap();
-        assert_eq!(cpu.get_policy().unwrap().max, max);
-    } else {
-        match cpu.modify_policy_max(min).unwrap_err() {
-            ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
-            error => panic!("Wrong error appeared: {}", error)
+        assert_eq!(cpu.get_freq_kernel().unwrap(), 1600000);
+        assert_eq!(cpu.get_driver().unwrap(), "acpi-cpufreq");
+
+        cpu.set_policy(&Policy::new(800000, 1600000, "powersave")).unwrap();
+        assert_eq!(sysfs.get(0, "scaling_max_freq"), "1600000");
+        assert_eq!(cpu.get_policy().unwrap().governor, "powersave");
+    }
+
+    #[test]
+    fn read_only_attribute_is_permission_denied() {
+        // root writes whatever the mode says
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        let path = sysfs.root().path().join("cpu0/cpufreq/scaling_max_freq");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+        match *Cpu::new_in(&sysfs.root(), 0).modify_policy_max(1600000).unwrap_err().kind() {
+            CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
         };
+        assert_eq!(sysfs.get(0, "scaling_max_freq"), "2400000\n");
+    }
+
+    #[test]
+    fn related_cpus_share_the_tree() {
+        let sysfs = FakeSysfs::new().with_cpu(0).with_cpu(1)
+            .set(0, "related_cpus", "0 1\n")
+            .set(1, "scaling_cur_freq", "800000\n");
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
+        let related = cpu.siblings(&cpu.get_related_cpus().unwrap());
+
+        assert_eq!(related.len(), 2);
+        assert_eq!(related[1].get_freq_kernel().unwrap(), 800000);
     }
 }
 
-#[test]
-fn modify_policy_min_can_modify_if_root() {
-    let cpu = Cpu::new(0);
+mod backend {
+    extern crate errno;
+    use ::backend::{ActiveBackend, Backend, active_backend};
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::memory::{CpuState, InMemory};
+    use ::policy::Policy;
+    use ::tunables::Tunable;
+    use std::sync::Arc;
 
-    let euid: libc::uid_t;
+    fn machine() -> Arc<InMemory> {
+        let mut cpu1 = CpuState::new(1);
+        cpu1.freq = 800000;
+        Arc::new(InMemory::new()
+            .with_cpu(0, CpuState::new(0))
+            .with_cpu(1, cpu1))
+    }
 
-    unsafe {
-        euid = libc::geteuid();
+    #[test]
+    fn get_all_with_returns_cpus_of_the_backend() {
+        let backend = machine();
+        let ids: Vec<u32> = Cpu::get_all_with(backend).map(|cpu| cpu.get_id()).collect();
+        assert_eq!(ids, vec![0, 1]);
     }
 
-    let (min, max) = cpu.get_hardware_limits().unwrap();
-    assert!(0 < min);
-    assert!(0 < max);
+    #[test]
+    fn cpu_forwards_calls_to_the_backend() {
+        let backend = machine();
+        let cpu = Cpu::with_backend(backend.clone(), 1);
 
-    if euid == 0 {
-        cpu.modify_policy_min(min).unwrap();
-        assert_eq!(cpu.get_policy().unwrap().min, min);
-        cpu.modify_policy_min(max).unwrap();
-        assert_eq!(cpu.get_policy().unwrap().min, max);
-    } else {
-        match cpu.modify_policy_min(min).unwrap_err() {
-            ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
-            error => panic!("Wrong error appeared: {}", error)
+        assert_eq!(cpu.get_freq().unwrap(), 800000);
+        assert_eq!(cpu.get_driver().unwrap(), "acpi-cpufreq");
+
+        cpu.set_policy(&Policy::new(800000, 1600000, "powersave")).unwrap();
+        assert_eq!(backend.state(1).unwrap().policy, Policy::new(800000, 1600000, "powersave"));
+        assert_eq!(backend.state(0).unwrap().policy.governor, "ondemand");
+    }
+
+    #[test]
+    fn missing_cpu_is_reported_as_cpu_not_found() {
+        let cpu = Cpu::with_backend(machine(), 2);
+        match *cpu.get_policy().unwrap_err().kind() {
+            CpuPowerError::CpuNotFound{id: 2} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
         };
     }
-}
 
-#[test]
-fn modify_policy_governor_can_modify_if_root() {
-    let cpu = Cpu::new(0);
+    #[test]
+    fn related_cpus_share_the_backend() {
+        let backend = machine();
+        let cpu = Cpu::with_backend(backend.clone(), 1);
+        let related = cpu.siblings(&cpu.get_related_cpus().unwrap());
+        assert_eq!(related.len(), 1);
+        assert!(Arc::ptr_eq(related[0].get_backend(), &(backend as Arc<dyn Backend>)));
+    }
 
-    let euid: libc::uid_t;
+    #[test]
+    fn active_backend_matches_build_configuration() {
+        let expected = if cfg!(cpufreq = "sysfs") {
+            ActiveBackend::Sysfs
+        } else if cfg!(cpufreq = "dynamic") {
+            active_backend()
+        } else {
+            ActiveBackend::Linked
+        };
 
-    unsafe {
-        euid = libc::geteuid();
+        assert_eq!(active_backend(), expected);
     }
 
-    let governors = cpu.get_available_governors().unwrap();
+    #[test]
+    fn raw_attributes_can_be_read_and_written() {
+        let backend = InMemory::new().with_attribute("cpufreq/boost", "1");
 
-    if euid == 0 {
-        for governor in governors {
-            cpu.modify_policy_governor(governor.as_ref()).unwrap();
-            assert_eq!(cpu.get_policy().unwrap().governor, governor);
-        }
-    } else {
-        match cpu.modify_policy_governor(governors.first().unwrap().as_ref()).unwrap_err() {
-            ::error::CpuPowerError::SystemError(errno::Errno(13)) => (),
+        assert_eq!(backend.list_directory("cpufreq").unwrap(), vec!["boost"]);
+        backend.write_attribute("cpufreq/boost", "0\n").unwrap();
+        assert_eq!(backend.read_attribute("cpufreq/boost").unwrap(), "0");
+
+        match backend.write_attribute("cpufreq/ondemand/up_threshold", "95").unwrap_err() {
+            CpuPowerError::NotSupported{errno: errno::Errno(2)} => (),
             error => panic!("Wrong error appeared: {}", error)
         };
     }
-}
 
-#[test]
-fn get_hardware_limit_returns_limits() {
-    let cpu = super::Cpu::new(0);
+    #[test]
+    fn attributes_and_operations_share_the_state() {
+        let backend = Arc::new(InMemory::new()
+            .with_cpu(0, CpuState::new(0))
+            .with_attribute("cpufreq/ondemand/up_threshold", "95"));
+        let cpu = Cpu::with_backend(backend.clone(), 0);
+
+        assert_eq!(backend.read_attribute("cpufreq/policy0/scaling_governor").unwrap(), "ondemand");
+        assert_eq!(cpu.get_freq_policy().unwrap().get_id(), 0);
+        assert_eq!(cpu.get_governor_tunables().unwrap().get(Tunable::UpThreshold).unwrap(), 95);
 
-    cpu.get_hardware_limits()
-        .map(|limits: (u64, u64)| assert!(limits.0 > 0 && limits.1 > limits.0) )
-        .unwrap();
+        cpu.modify_policy_governor("userspace").unwrap();
+        cpu.set_setspeed(1600000, false).unwrap();
+        assert_eq!(backend.state(0).unwrap().freq, 1600000);
+        assert_eq!(backend.read_attribute("cpu0/cpufreq/scaling_setspeed").unwrap(), "1600000");
+
+        backend.write_attribute("cpufreq/policy0/scaling_max_freq", "1600000").unwrap();
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 1600000, "userspace"));
+    }
+
+    #[test]
+    fn inconsistent_writes_are_rejected() {
+        let backend = machine();
+        let cpu = Cpu::with_backend(backend.clone(), 0);
+
+        let results = [
+            backend.write_attribute("cpu0/cpufreq/scaling_min_freq", "3000000"),
+            backend.write_attribute("cpu0/cpufreq/scaling_max_freq", "700000"),
+            backend.write_attribute("cpu0/cpufreq/scaling_governor", "schedutil"),
+            backend.set_policy(0, &Policy::new(1600000, 800000, "ondemand")),
+            backend.set_policy(0, &Policy::new(800000, 1600000, "schedutil"))
+        ];
+
+        for result in results.iter() {
+            match *result {
+                Err(CpuPowerError::InvalidValue{errno: errno::Errno(22)}) => (),
+                ref other => panic!("Wrong result: {:?}", other)
+            };
+        }
+
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 2400000, "ondemand"));
+    }
+
+    #[test]
+    fn set_freq_switches_to_userspace() {
+        let backend = machine();
+        let cpu = Cpu::with_backend(backend.clone(), 0);
+
+        cpu.set_freq(1600000).unwrap();
+        assert_eq!(backend.state(0).unwrap().policy.governor, "userspace");
+        assert_eq!(cpu.get_setspeed().unwrap(), 1600000);
+        assert_eq!(cpu.get_freq().unwrap(), 1600000);
+    }
+}
+
+#[cfg(cpufreq = "dynamic")]
+mod dynamic {
+    extern crate errno;
+    extern crate libc;
+    use ::backend::{ActiveBackend, Backend, active_backend, default_backend};
+    use ::base::{self, LIBRARY_NAMES, first_loadable};
+    use ::error::CpuPowerError;
+    use ::libcpupower::LibCpupower;
+    use std::process::Command;
+
+    /// Whether pkg-config knows the library, which is then installed with its symlink
+    fn is_installed(name: &str) -> bool {
+        Command::new("pkg-config").arg("--exists").arg(name).status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn loads_an_installed_library() {
+        let installed = is_installed("libcpupower") || is_installed("libcpufreq");
+
+        match active_backend() {
+            ActiveBackend::Loaded(name) => {
+                assert!(installed, "{} loaded but pkg-config doesn't know it", name);
+                assert!(LIBRARY_NAMES.contains(&name));
+
+                // Calls reach the library instead of failing with ENOSYS
+                errno::set_errno(errno::Errno(0));
+                unsafe { base::cpufreq_cpu_exists(0) };
+                assert!(errno::errno().0 != libc::ENOSYS);
+            },
+            ActiveBackend::Sysfs => {
+                assert!(!installed, "pkg-config knows the library but it wasn't loaded");
+                assert!(format!("{:?}", default_backend()).starts_with("SysfsRoot"));
+                assert!(!LibCpupower.cpu_exists(0));
+
+                match LibCpupower.get_driver(0).unwrap_err() {
+                    CpuPowerError::NotSupported{errno: errno::Errno(38)} => (),
+                    error => panic!("Wrong error appeared: {}", error)
+                };
+            },
+            backend => panic!("Wrong backend {:?}", backend)
+        }
+    }
+
+    #[test]
+    fn libraries_missing_or_without_the_functions_are_skipped() {
+        assert_eq!(first_loadable(&["libcpufreq-does-not-exist.so"]), None);
+        assert_eq!(first_loadable(&["libc.so.6"]), None);
+        assert_eq!(first_loadable(&["libc.so.6", "libcpufreq-does-not-exist.so"]), None);
+    }
+}
+
+mod simulator {
+    extern crate errno;
+    use ::backend::Backend;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::policy::Policy;
+    use ::simulator::{SimulatedPolicy, Simulator};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn machine() -> Arc<Simulator> {
+        Arc::new(Simulator::new()
+            .with_policy(SimulatedPolicy::new(&[0, 1]))
+            .with_policy(SimulatedPolicy::new(&[2, 3])
+                         .frequencies(&[1000000, 2000000, 3000000])
+                         .driver("cppc_cpufreq")
+                         .governor("performance")))
+    }
+
+    #[test]
+    fn with_cpus_creates_one_policy_per_cpu() {
+        let simulator = Arc::new(Simulator::with_cpus(4));
+        assert_eq!(Cpu::get_all_with(simulator.clone()).count(), 4);
+        assert_eq!(Cpu::with_backend(simulator, 3).get_related_cpus().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn policies_are_shared_by_related_cpus() {
+        let simulator = machine();
+        Cpu::with_backend(simulator.clone(), 0).modify_policy_max(1600000).unwrap();
+
+        assert_eq!(Cpu::with_backend(simulator.clone(), 1).get_policy().unwrap().max, 1600000);
+        assert_eq!(Cpu::with_backend(simulator.clone(), 2).get_policy().unwrap().max, 3000000);
+        let related: Vec<u32> = Cpu::with_backend(simulator, 3).get_related_cpus().unwrap()
+            .into_iter().collect();
+        assert_eq!(related, vec![2, 3]);
+    }
+
+    #[test]
+    fn frequency_table_and_driver_are_configurable() {
+        let cpu = Cpu::with_backend(machine(), 2);
+        assert_eq!(cpu.get_available_frequencies().unwrap(), vec![3000000, 2000000, 1000000]);
+        assert_eq!(cpu.get_hardware_limits().unwrap(), (1000000, 3000000));
+        assert_eq!(cpu.get_driver().unwrap(), "cppc_cpufreq");
+        assert_eq!(cpu.get_freq().unwrap(), 3000000);
+    }
+
+    #[test]
+    fn limits_are_clamped_to_hardware_limits() {
+        let cpu = Cpu::with_backend(machine(), 0);
+        cpu.modify_policy_min(100000).unwrap();
+        cpu.modify_policy_max(9000000).unwrap();
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 2400000, "ondemand"));
+    }
+
+    #[test]
+    fn governors_pick_the_frequency() {
+        let cpu = Cpu::with_backend(machine(), 0);
+        cpu.set_policy(&Policy::new(1600000, 2400000, "powersave")).unwrap();
+        assert_eq!(cpu.get_freq().unwrap(), 1600000);
+        cpu.modify_policy_governor("performance").unwrap();
+        assert_eq!(cpu.get_freq().unwrap(), 2400000);
+        cpu.modify_policy_max(1600000).unwrap();
+        assert_eq!(cpu.get_freq().unwrap(), 1600000);
+    }
+
+    #[test]
+    fn unknown_governor_is_rejected() {
+        let cpu = Cpu::with_backend(machine(), 0);
+        match *cpu.modify_policy_governor("schedutil").unwrap_err().kind() {
+            CpuPowerError::GovernorUnavailable{ref governor, errno: errno::Errno(22)} if governor == "schedutil" => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn setspeed_requires_userspace_governor() {
+        let simulator = machine();
+        match simulator.set_setspeed(0, 800000).unwrap_err() {
+            CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(simulator.get_setspeed(0).unwrap(), None);
+
+        Cpu::with_backend(simulator.clone(), 0).modify_policy_governor("userspace").unwrap();
+        simulator.set_setspeed(0, 1500000).unwrap();
+        assert_eq!(simulator.get_setspeed(0).unwrap(), Some(1500000));
+        assert_eq!(Cpu::with_backend(simulator, 1).get_freq().unwrap(), 1600000);
+    }
+
+    #[test]
+    fn set_freq_switches_to_userspace_governor() {
+        let cpu = Cpu::with_backend(machine(), 0);
+        cpu.set_freq(800000).unwrap();
+        assert_eq!(cpu.get_policy().unwrap().governor, "userspace");
+        assert_eq!(cpu.get_freq().unwrap(), 800000);
+    }
+
+    #[test]
+    fn stats_advance_with_virtual_time() {
+        let simulator = machine();
+        let cpu = Cpu::with_backend(simulator.clone(), 0);
+
+        simulator.advance(Duration::from_millis(1000));
+        cpu.modify_policy_governor("powersave").unwrap();
+        simulator.advance(Duration::from_millis(250));
+
+        let stats: Vec<(u64, u64)> = cpu.get_stats().unwrap().iter()
+            .map(|stat| (stat.freq, stat.time_in_state)).collect();
+        assert_eq!(stats, vec![(2400000, 100), (1600000, 0), (800000, 25)]);
+        assert_eq!(cpu.get_transitions().unwrap(), 1);
+    }
+
+    #[test]
+    fn short_advances_add_up() {
+        let simulator = machine();
+
+        for _ in 0..1000 {
+            simulator.advance(Duration::from_micros(100));
+        }
+
+        let stats = Cpu::with_backend(simulator, 0).get_stats().unwrap();
+        assert_eq!((stats[0].freq, stats[0].time_in_state), (2400000, 10));
+    }
+
+    #[test]
+    fn min_never_exceeds_max() {
+        let simulator = machine();
+        let cpu = Cpu::with_backend(simulator.clone(), 0);
+
+        cpu.modify_policy_max(1600000).unwrap();
+        cpu.modify_policy_min(2400000).unwrap();
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(1600000, 1600000, "ondemand"));
+        assert_eq!(simulator.read_attribute("cpu0/cpufreq/scaling_min_freq").unwrap(), "1600000");
+
+        cpu.modify_policy_max(800000).unwrap();
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 800000, "ondemand"));
+    }
+
+    #[test]
+    fn set_policy_with_unknown_governor_changes_nothing() {
+        let cpu = Cpu::with_backend(machine(), 0);
+
+        match *cpu.set_policy(&Policy::new(1600000, 1600000, "schedutil")).unwrap_err().kind() {
+            CpuPowerError::GovernorUnavailable{ref governor, errno: errno::Errno(22)} if governor == "schedutil" => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 2400000, "ondemand"));
+    }
+
+    #[test]
+    fn attributes_follow_kernel_rules() {
+        let simulator = machine();
+
+        assert_eq!(simulator.read_attribute("cpu1/cpufreq/affected_cpus").unwrap(), "0 1");
+        assert_eq!(simulator.read_attribute("cpu0/cpufreq/scaling_setspeed").unwrap(), "<unsupported>");
+
+        match simulator.write_attribute("cpu0/cpufreq/scaling_setspeed", "800000").unwrap_err() {
+            CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+
+        simulator.write_attribute("cpu0/cpufreq/scaling_max_freq", "9000000").unwrap();
+        assert_eq!(simulator.read_attribute("cpu0/cpufreq/scaling_max_freq").unwrap(), "2400000");
+
+        match simulator.write_attribute("cpu0/cpufreq/cpuinfo_max_freq", "800000").unwrap_err() {
+            CpuPowerError::PermissionDenied{errno: errno::Errno(13)} => (),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+}
+
+mod fixture {
+    extern crate errno;
+    use super::FakeSysfs;
+    use ::backend::Backend;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::fixture::Fixture;
+    use ::simulator::Simulator;
+    use std::fs;
+    use std::sync::Arc;
+
+    fn machine() -> FakeSysfs {
+        let sysfs = FakeSysfs::new().with_cpu(0).with_cpu(1);
+        fs::write(sysfs.root().path().join("online"), "0-1\n").unwrap();
+        sysfs
+    }
+
+    #[test]
+    fn capture_records_cpufreq_attributes() {
+        let fixture = Fixture::capture(&machine().root()).unwrap();
+        let attributes = fixture.attributes();
+
+        assert_eq!(attributes["online"], "0-1");
+        assert_eq!(attributes["cpu1/cpufreq/related_cpus"], "1");
+        assert_eq!(attributes["cpu0/cpufreq/stats/time_in_state"], "2400000 1000\n1600000 200\n800000 30");
+        assert_eq!(attributes["cpu0/cpufreq/scaling_available_governors"],
+                   "conservative ondemand userspace powersave performance");
+    }
+
+    #[test]
+    fn capture_works_on_any_backend() {
+        let fixture = Fixture::capture(&Simulator::with_cpus(2)).unwrap();
+        assert_eq!(fixture.attributes()["cpu1/cpufreq/scaling_governor"], "ondemand");
+        assert_eq!(fixture.attributes()["cpu1/cpufreq/stats/total_trans"], "0");
+    }
+
+    #[test]
+    fn fixture_acts_as_backend() {
+        let fixture = Arc::new(Fixture::capture(&machine().root()).unwrap());
+
+        let ids: Vec<u32> = Cpu::get_all_with(fixture.clone()).map(|cpu| cpu.get_id()).collect();
+        assert_eq!(ids, vec![0, 1]);
+
+        let cpu = Cpu::with_backend(fixture, 1);
+        assert_eq!(cpu.get_policy().unwrap().governor, "ondemand");
+        assert_eq!(cpu.get_available_frequencies().unwrap(), vec![2400000, 1600000, 800000]);
+        assert_eq!(cpu.get_stats().unwrap()[1].time_in_state, 200);
+        assert_eq!(cpu.get_transitions().unwrap(), 42);
+    }
+
+    #[test]
+    fn fixture_is_read_only() {
+        let fixture = Fixture::capture(&machine().root()).unwrap();
+        match fixture.modify_policy_max(0, 1600000).unwrap_err() {
+            CpuPowerError::PermissionDenied{errno: errno::Errno(30)} => (),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn directory_round_trip() {
+        let fixture = Fixture::capture(&machine().root()).unwrap();
+        let copy = FakeSysfs::new();
+
+        fixture.save_dir(copy.root().path()).unwrap();
+        assert_eq!(Fixture::load_dir(copy.root().path()).unwrap(), fixture);
+        assert_eq!(copy.get(0, "stats/total_trans"), "42\n");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let fixture = Fixture::capture(&machine().root())
+            .unwrap()
+            .with_attribute("cpufreq/policy0/quoted", "\"a\\b\"\tc \u{1} \u{e9}");
+        let copy = FakeSysfs::new();
+        let path = copy.root().path().join("fixture.json");
+
+        fixture.save_json(&path).unwrap();
+        assert_eq!(Fixture::load_json(&path).unwrap(), fixture);
+    }
+
+    #[test]
+    fn from_json_decodes_escapes() {
+        let fixture = Fixture::from_json(r#"{"cpu0/cpufreq/x": "é😀\/\n"}"#).unwrap();
+        assert_eq!(fixture.attributes()["cpu0/cpufreq/x"], "\u{e9}\u{1f600}/\n");
+    }
+
+    #[test]
+    fn from_json_rejects_malformed_input() {
+        for json in &["", "{", r#"{"a": 1}"#, r#"{"a": "b",}"#, r#"{"a": "b"} x"#, r#"{"a": "\ud800"}"#] {
+            match Fixture::from_json(json).unwrap_err() {
+                CpuPowerError::MalformedFixture{offset: _, reason: _} => (),
+                error => panic!("Wrong error appeared for {:?}: {}", json, error)
+            };
+        }
+    }
+}
+
+mod threads {
+    extern crate errno;
+    use super::FakeSysfs;
+    use ::cpu::{self, Cpu};
+    use ::error::CpuPowerError;
+    use ::fixture::Fixture;
+    use ::memory::InMemory;
+    use ::policy::Policy;
+    use ::simulator::Simulator;
+    use ::sysfs::SysfsRoot;
+    use std::sync::Arc;
+    use std::thread;
+
+    const THREADS: u32 = 8;
+    const ITERATIONS: u32 = 200;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn expect_errno<T: ::std::fmt::Debug>(result: ::result::Result<T>, expected: i32) {
+        let error = result.unwrap_err();
+        assert_eq!(error.errno(), Some(errno::Errno(expected)), "Wrong error appeared: {}", error);
+    }
+
+    #[test]
+    fn public_types_are_send_and_sync() {
+        assert_send_sync::<Cpu>();
+        assert_send_sync::<cpu::Iterator>();
+        assert_send_sync::<CpuPowerError>();
+        assert_send_sync::<Policy>();
+        assert_send_sync::<SysfsRoot>();
+        assert_send_sync::<Fixture>();
+        assert_send_sync::<InMemory>();
+        assert_send_sync::<Simulator>();
+    }
+
+    #[test]
+    fn errors_are_reported_to_the_thread_making_the_call() {
+        let sysfs = Arc::new(FakeSysfs::new().with_cpu(0).set(1, "scaling_driver", "acpi-cpufreq\n"));
+
+        let handles: Vec<_> = (0..THREADS).map(|thread| {
+            let sysfs = sysfs.clone();
+            thread::spawn(move || {
+                let present = Cpu::new_in(&sysfs.root(), 0);
+                let missing = Cpu::new_in(&sysfs.root(), 1);
+
+                for iteration in 0..ITERATIONS {
+                    if (iteration + thread) % 2 == 0 {
+                        assert_eq!(present.get_freq_kernel().unwrap(), 2400000);
+                    } else {
+                        expect_errno(missing.get_freq_kernel(), 2);
+                    }
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn shared_cpu_can_be_driven_from_many_threads() {
+        let simulator = Arc::new(Simulator::with_cpus(1));
+        let cpu = Cpu::with_backend(simulator, 0);
+
+        let handles: Vec<_> = (0..THREADS).map(|thread| {
+            let cpu = cpu.clone();
+            thread::spawn(move || {
+                for iteration in 0..ITERATIONS {
+                    let freq = if (iteration + thread) % 2 == 0 { 800000 } else { 2400000 };
+                    cpu.set_freq(freq).unwrap();
+
+                    let policy = cpu.get_policy().unwrap();
+                    assert_eq!(policy.governor, "userspace");
+                    assert!(policy.min <= policy.max);
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let freq = cpu.get_freq().unwrap();
+        assert!(freq == 800000 || freq == 2400000);
+    }
+
+    #[cfg(cpufreq = "mock")]
+    #[test]
+    fn library_errno_is_captured_per_call() {
+        use ::backend::Backend;
+        use ::libcpupower::{LibCpupower, call};
+
+        errno::set_errno(errno::Errno(13));
+        assert_eq!(call(|| 0).1, errno::Errno(0));
+
+        let handles: Vec<_> = (0..THREADS).map(|thread| {
+            thread::spawn(move || {
+                for iteration in 0..ITERATIONS {
+                    match (iteration + thread) % 3 {
+                        0 => assert!(LibCpupower.get_freq_kernel(0).is_ok()),
+                        1 => expect_errno(LibCpupower.get_hardware_limits(1), 13),
+                        _ => expect_errno(LibCpupower.get_policy(5), 2)
+                    }
+                }
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+mod errors {
+    extern crate errno;
+    use super::FakeSysfs;
+    use ::cpu::Cpu;
+    use ::error::{CpuPowerError, ErrorContext, Operation};
+    use ::fixture::Fixture;
+    use std::error::Error;
+    use std::fs;
+    use std::io;
+    use std::sync::Arc;
+
+    fn machine() -> FakeSysfs {
+        let sysfs = FakeSysfs::new().with_cpu(0);
+        fs::create_dir_all(sysfs.root().path().join("cpu1")).unwrap();
+        fs::write(sysfs.root().path().join("cpu1/online"), "0\n").unwrap();
+        fs::create_dir_all(sysfs.root().path().join("cpu2")).unwrap();
+        fs::write(sysfs.root().path().join("cpu2/online"), "1\n").unwrap();
+        sysfs
+    }
+
+    #[test]
+    fn io_errors_without_errno_map_by_kind() {
+        let cases = [(io::ErrorKind::NotFound, Some(2)), (io::ErrorKind::PermissionDenied, Some(13)),
+                     (io::ErrorKind::InvalidData, Some(22)), (io::ErrorKind::InvalidInput, Some(22)),
+                     (io::ErrorKind::UnexpectedEof, None)];
+
+        for &(kind, code) in &cases {
+            let error = CpuPowerError::from(io::Error::new(kind, "fake"));
+            assert_eq!(error.errno(), code.map(errno::Errno), "{:?} mapped to {:?}", kind, error);
+        }
+
+        match CpuPowerError::from(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated")) {
+            ref error @ CpuPowerError::IoError(_) => assert_eq!(error.source().unwrap().to_string(), "truncated"),
+            error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn errno_values_map_to_dedicated_variants() {
+        let cases = [(1, "PermissionDenied"), (13, "PermissionDenied"), (30, "PermissionDenied"),
+                     (2, "NotSupported"), (38, "NotSupported"), (95, "NotSupported"),
+                     (22, "InvalidValue"), (34, "InvalidValue"), (16, "SystemError")];
+
+        for &(code, variant) in &cases {
+            let error = CpuPowerError::from_errno(errno::Errno(code));
+            assert!(format!("{:?}", error).starts_with(variant), "{} mapped to {:?}", code, error);
+            assert_eq!(error.errno(), Some(errno::Errno(code)));
+        }
+    }
+
+    #[test]
+    fn missing_cpu_is_reported_as_cpu_not_found() {
+        match *Cpu::new_in(&machine().root(), 7).get_policy().unwrap_err().kind() {
+            CpuPowerError::CpuNotFound{id: 7} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn offline_cpu_is_reported_as_cpu_offline() {
+        match *Cpu::new_in(&machine().root(), 1).get_freq_kernel().unwrap_err().kind() {
+            CpuPowerError::CpuOffline{id: 1, errno: errno::Errno(2)} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn cpu_without_cpufreq_is_reported_as_driver_missing() {
+        match *Cpu::new_in(&machine().root(), 2).get_policy().unwrap_err().kind() {
+            CpuPowerError::DriverMissing{id: 2, errno: errno::Errno(2)} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn attribute_missing_on_working_cpu_is_reported_as_not_supported() {
+        let sysfs = machine();
+        fs::remove_file(sysfs.root().path().join("cpu0/cpufreq/stats/total_trans")).unwrap();
+
+        match *Cpu::new_in(&sysfs.root(), 0).get_transitions().unwrap_err().kind() {
+            CpuPowerError::NotSupported{errno: errno::Errno(2)} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn unknown_governor_is_reported_as_unavailable() {
+        let sysfs = machine();
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
+
+        match *cpu.modify_policy_governor("power save").unwrap_err().kind() {
+            CpuPowerError::GovernorUnavailable{ref governor, errno: errno::Errno(22)} if governor == "power save" => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(sysfs.get(0, "scaling_governor"), "ondemand\n");
+    }
+
+    #[test]
+    fn rejected_write_tells_attribute_and_value() {
+        let fixture = Arc::new(Fixture::capture(&machine().root()).unwrap());
+        let error = Cpu::with_backend(fixture, 0).modify_policy_max(1600000).unwrap_err();
+
+        assert_eq!(error.context(), Some(&ErrorContext {
+            cpu: Some(0),
+            policy: None,
+            operation: Operation::Write,
+            attribute: String::from("scaling_max_freq"),
+            value: Some(String::from("1600000"))
+        }));
+        assert_eq!(error.errno(), Some(errno::Errno(30)));
+        assert!(error.to_string().starts_with("Can't write \"1600000\" to scaling_max_freq of cpu 0: "),
+                "{}", error);
+        assert!(error.to_string().contains("root privileges"), "{}", error);
+    }
+
+    #[test]
+    fn failed_read_tells_attribute() {
+        let error = Cpu::new_in(&machine().root(), 1).get_driver().unwrap_err();
+        let context = error.context().unwrap();
+
+        assert_eq!((context.cpu, context.operation, context.attribute.as_str()), (Some(1), Operation::Read, "scaling_driver"));
+        assert_eq!(context.value, None);
+        assert!(error.to_string().starts_with("Can't read scaling_driver of cpu 1: Cpu 1 is offline"), "{}", error);
+        assert_eq!(error.source().unwrap().to_string(), error.kind().to_string());
+    }
+
+    #[test]
+    fn parse_errors_keep_their_source() {
+        let sysfs = machine().set(0, "stats/total_trans", "many\n");
+        let error = Cpu::new_in(&sysfs.root(), 0).get_transitions().unwrap_err();
+
+        assert!(error.source().is_some());
+        assert_eq!(error.errno(), None);
+    }
+}
+
+
+mod freq_policy {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+    use ::policy::Policy;
+    use ::simulator::{SimulatedPolicy, Simulator};
+    use std::sync::Arc;
+
+    #[test]
+    fn get_all_in_lists_policies_in_order() {
+        let sysfs = Machine::new().build().with_policy(10, &[10]);
+        let policies = FreqPolicy::get_all_in(&sysfs.root()).unwrap();
+
+        assert_eq!(policies.iter().map(FreqPolicy::get_id).collect::<Vec<u32>>(), vec![0, 2, 10]);
+    }
+
+    #[test]
+    fn member_cpus_are_read_from_the_policy() {
+        let sysfs = Machine::new().set(2, "affected_cpus", "2\n").build();
+        let policy = FreqPolicy::new_in(&sysfs.root(), 2);
+
+        assert_eq!(policy.get_related_cpus().unwrap().to_string(), "2-3");
+        assert_eq!(policy.get_affected_cpus().unwrap().to_string(), "2");
+        assert_eq!(policy.cpus(&policy.get_related_cpus().unwrap())[1].get_id(), 3);
+        assert_eq!(policy.get_driver().unwrap(), "acpi-cpufreq");
+        assert_eq!(policy.get_hardware_limits().unwrap(), (800000, 2400000));
+        assert_eq!(policy.get_transitions().unwrap(), 42);
+        assert_eq!(policy.get_stats().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn modifying_the_policy_applies_to_every_member() {
+        let sysfs = Machine::new().build();
+        let policy = FreqPolicy::new_in(&sysfs.root(), 0);
+
+        policy.modify_policy_max(1600000).unwrap();
+        policy.modify_policy_governor("powersave").unwrap();
+
+        for id in 0..2 {
+            let cpu = Cpu::new_in(&sysfs.root(), id);
+            assert_eq!(cpu.get_policy().unwrap(), Policy::new(800000, 1600000, "powersave"));
+        }
+        assert_eq!(Cpu::new_in(&sysfs.root(), 2).get_policy().unwrap().max, 2400000);
+    }
+
+    #[test]
+    fn of_cpu_finds_the_policy_of_a_member() {
+        let sysfs = Machine::new().build();
+
+        assert_eq!(Cpu::new_in(&sysfs.root(), 3).get_freq_policy().unwrap().get_id(), 2);
+        match FreqPolicy::of_cpu(&Cpu::new_in(&sysfs.root(), 4)).unwrap_err() {
+            CpuPowerError::CpuNotFound { id: 4 } => {},
+            error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn simulated_policies_are_exposed() {
+        let simulator = Arc::new(Simulator::new()
+            .with_policy(SimulatedPolicy::new(&[0, 1]))
+            .with_policy(SimulatedPolicy::new(&[2, 3])));
+        let policies = FreqPolicy::get_all_with(simulator.clone()).unwrap();
+
+        assert_eq!(policies.iter().map(FreqPolicy::get_id).collect::<Vec<u32>>(), vec![0, 2]);
+
+        let limits = policies[1].get_hardware_limits().unwrap();
+        policies[1].set_policy(&Policy::new(limits.0, limits.0, "powersave")).unwrap();
+        assert_eq!(Cpu::with_backend(simulator.clone(), 3).get_policy().unwrap().max, limits.0);
+        assert_eq!(Cpu::with_backend(simulator, 1).get_policy().unwrap().max, limits.1);
+    }
+
+    #[test]
+    fn errors_match_those_of_cpu() {
+        let simulator = Arc::new(Simulator::new().with_policy(SimulatedPolicy::new(&[0, 1])));
+        let policy = FreqPolicy::with_backend(simulator.clone(), 0);
+        let error = policy.modify_policy_governor("schedutil").unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::GovernorUnavailable{ref governor, ..} if governor == "schedutil" => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(error.context().unwrap().policy, Some(0));
+        assert!(error.to_string().starts_with("Can't write \"schedutil\" to scaling_governor of policy 0: "),
+                "{}", error);
+
+        let cpu_error = Cpu::with_backend(simulator, 1).modify_policy_governor("schedutil").unwrap_err();
+        assert_eq!(cpu_error.kind().to_string(), error.kind().to_string());
+
+        match *FreqPolicy::new_in(&Machine::new().build().root(), 4).get_policy().unwrap_err().kind() {
+            CpuPowerError::CpuNotFound{id: 4} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+    }
+
+    #[test]
+    fn errors_are_diagnosed_on_an_online_member() {
+        let sysfs = Machine::new().with_offline(2).build();
+        let error = FreqPolicy::new_in(&sysfs.root(), 2).get_amd_perf().unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::NotSupported{..} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+        assert_eq!(error.context().unwrap().cpu, Some(3));
+        assert_eq!(error.context().unwrap().policy, Some(2));
+    }
+}
+
+
+mod masks {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::memory::{CpuState, InMemory};
+    use ::types::CpuMask;
+    use std::sync::Arc;
+
+    fn ids<I: Iterator<Item = Cpu>>(cpus: I) -> Vec<u32> {
+        cpus.map(|cpu| cpu.get_id()).collect()
+    }
+
+    #[test]
+    fn masks_are_read_when_available() {
+        // cpu2 is offline, cpus 4 to 7 could be plugged in later
+        let sysfs = Machine::new().with_cpus(&[0, 1, 3]).with_offline(2)
+            .with_file("possible", "0-7\n")
+            .with_file("present", "0-3\n")
+            .with_file("online", "0-1,3\n")
+            .with_file("offline", "2,4-7\n")
+            .build();
+        let root = sysfs.root();
+
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Possible)), (0..8).collect::<Vec<u32>>());
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Present)), vec![0, 1, 2, 3]);
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Online)), vec![0, 1, 3]);
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Offline)), vec![2, 4, 5, 6, 7]);
+        assert_eq!(ids(Cpu::get_all_in(&root)), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn cpus_above_a_gap_are_not_lost_without_masks() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 3]).with_offline(2).build();
+        let root = sysfs.root();
+
+        assert_eq!(ids(Cpu::get_all_in(&root)), vec![0, 1, 2, 3]);
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Online)), vec![0, 1, 3]);
+        assert_eq!(ids(Cpu::get_mask_in(&root, CpuMask::Offline)), vec![2]);
+    }
+
+    #[test]
+    fn get_all_skips_absent_cpus() {
+        // cpu3 isn't there at all, cpu4 is
+        let with_mask = Machine::new().with_cpus(&[0, 1, 2, 4]).with_file("present", "0-2,4\n").build();
+        let without_mask = Machine::new().with_cpus(&[0, 1, 2, 4]).build();
+
+        assert_eq!(ids(Cpu::get_all_in(&with_mask.root())), vec![0, 1, 2, 4]);
+        assert_eq!(ids(Cpu::get_all_in(&without_mask.root())), vec![0, 1, 2, 4]);
+        assert!(!Cpu::exists_in(&without_mask.root(), 3));
+    }
+
+    #[test]
+    fn is_online_reads_the_cpu_state() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 3]).with_offline(2).build();
+
+        assert!(Cpu::new_in(&sysfs.root(), 0).is_online().unwrap());
+        assert!(Cpu::new_in(&sysfs.root(), 1).is_online().unwrap());
+        assert!(!Cpu::new_in(&sysfs.root(), 2).is_online().unwrap());
+
+        match *Cpu::new_in(&sysfs.root(), 9).is_online().unwrap_err().kind() {
+            CpuPowerError::CpuNotFound { id: 9 } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn backends_without_tree_list_their_cpus() {
+        let backend = Arc::new(InMemory::new()
+            .with_cpu(0, CpuState::new(0))
+            .with_cpu(2, CpuState::new(2)));
+
+        assert_eq!(ids(Cpu::get_all_with(backend.clone())), vec![0, 2]);
+        assert_eq!(ids(Cpu::get_mask_with(backend, CpuMask::Offline)), Vec::<u32>::new());
+    }
+}
+
+
+mod cpu_set {
+    extern crate errno;
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::error::CpuPowerError;
+    use ::memory::{CpuState, InMemory};
+    use std::sync::Arc;
+
+    fn set(ids: &[u32]) -> CpuSet {
+        CpuSet::from(ids)
+    }
+
+    #[test]
+    fn cpu_lists_are_parsed() {
+        assert_eq!(CpuSet::from_cpu_list("0-3,6,8-9\n").unwrap(), set(&[0, 1, 2, 3, 6, 8, 9]));
+        assert_eq!("0 1 2 3".parse::<CpuSet>().unwrap(), set(&[0, 1, 2, 3]));
+        assert_eq!(CpuSet::from_cpu_list("5").unwrap(), set(&[5]));
+        assert!(CpuSet::from_cpu_list("\n").unwrap().is_empty());
+        assert!(CpuSet::from_cpu_list("3-1").is_err());
+        assert!(CpuSet::from_cpu_list("0,a").is_err());
+    }
+
+    #[test]
+    fn huge_cpu_ids_are_rejected() {
+        assert_eq!(CpuSet::from_cpu_list("65534-65535").unwrap().len(), 2);
+
+        for list in &["0-4294967295", "65536", "0,65536-65537"] {
+            match CpuSet::from_cpu_list(list).unwrap_err() {
+                CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
+                error => panic!("Wrong error appeared for {:?}: {}", list, error)
+            };
+        }
+    }
+
+    #[test]
+    fn cpu_lists_are_formatted_with_ranges() {
+        assert_eq!(set(&[0, 1, 2, 3, 8, 10, 11]).to_cpu_list(), "0-3,8,10-11");
+        assert_eq!(set(&[4]).to_string(), "4");
+        assert_eq!(CpuSet::new().to_string(), "");
+    }
+
+    #[test]
+    fn cpu_masks_are_parsed_and_formatted() {
+        let cpus = set(&[0, 1, 2, 3, 8, 10, 11, 32]);
+
+        assert_eq!(cpus.to_cpu_mask(), "00000001,00000d0f");
+        assert_eq!(CpuSet::from_cpu_mask("00000001,00000d0f\n").unwrap(), cpus);
+        assert_eq!(CpuSet::from_cpu_mask("ff").unwrap(), set(&[0, 1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(CpuSet::new().to_cpu_mask(), "00000000");
+        assert!(CpuSet::from_cpu_mask("").is_err());
+        assert!(CpuSet::from_cpu_mask("0x3").is_err());
+    }
+
+    #[test]
+    fn malformed_or_huge_cpu_masks_are_rejected() {
+        let zeros = vec!["00000000"; 2047].join(",");
+
+        for mask in &["00000001,d0f", "1,", ",00000001", "000000001,00000000", "00000001,,00000000"] {
+            match CpuSet::from_cpu_mask(mask).unwrap_err() {
+                CpuPowerError::InvalidValue{errno: errno::Errno(22)} => (),
+                error => panic!("Wrong error appeared for {:?}: {}", mask, error)
+            };
+        }
+
+        assert_eq!(CpuSet::from_cpu_mask(&format!("80000000,{}", zeros)).unwrap(), set(&[65535]));
+        assert!(CpuSet::from_cpu_mask(&format!("1,{},{}", zeros, "00000000")).is_err());
+    }
+
+    #[test]
+    fn set_algebra() {
+        let first = set(&[0, 1, 2, 3]);
+        let second = set(&[2, 3, 4]);
+
+        assert_eq!(first.union(&second), set(&[0, 1, 2, 3, 4]));
+        assert_eq!(first.intersection(&second), set(&[2, 3]));
+        assert_eq!(first.difference(&second), set(&[0, 1]));
+        assert!(set(&[2, 3]).is_subset(&first));
+        assert_eq!(second.first(), Some(2));
+    }
+
+    #[test]
+    fn cpus_convert_both_ways() {
+        let backend = Arc::new(InMemory::new()
+            .with_cpu(0, CpuState::new(0))
+            .with_cpu(1, CpuState::new(1)));
+        let cpus = set(&[0, 1]).to_cpus_with(backend);
+
+        assert_eq!(cpus.iter().map(Cpu::get_id).collect::<Vec<u32>>(), vec![0, 1]);
+        assert_eq!(cpus[1].get_freq_kernel().unwrap(), 2400000);
+        assert_eq!(CpuSet::from(cpus), set(&[0, 1]));
+    }
+}
+
+
+mod bulk {
+    use super::Machine;
+    use ::backend::Backend;
+    use ::bulk::Bulk;
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::error::CpuPowerError;
+    use ::policy::Policy;
+    use ::result::Result;
+    use ::simulator::{SimulatedPolicy, Simulator};
+    use std::sync::{Arc, Mutex};
+
+    /// Records the writes reaching the simulator
+    #[derive(Debug)]
+    struct Recorder {
+        simulator: Simulator,
+        writes: Mutex<Vec<String>>
+    }
+
+    impl Backend for Recorder {
+        fn read_attribute(&self, path: &str) -> Result<String> {
+            self.simulator.read_attribute(path)
+        }
+
+        fn write_attribute(&self, path: &str, value: &str) -> Result<()> {
+            self.writes.lock().unwrap().push(format!("{}={}", path, value));
+            self.simulator.write_attribute(path, value)
+        }
+
+        fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+            self.simulator.list_directory(path)
+        }
+    }
+
+    fn machine() -> Arc<Recorder> {
+        Arc::new(Recorder {
+            simulator: Simulator::new()
+                .with_policy(SimulatedPolicy::new(&[0, 1, 2, 3]))
+                .with_policy(SimulatedPolicy::new(&[4, 5]))
+                .with_policy(SimulatedPolicy::new(&[6])),
+            writes: Mutex::new(Vec::new())
+        })
+    }
+
+    #[test]
+    fn operations_run_once_per_policy() {
+        let backend = machine();
+        let report = Bulk::all_with(backend.clone()).set_governor("powersave");
+
+        assert!(report.is_ok());
+        assert_eq!(report.iter().count(), 7);
+        assert_eq!(report.groups().len(), 3);
+
+        let mut writes = backend.writes.lock().unwrap().clone();
+        writes.sort();
+        assert_eq!(writes, vec!["cpu0/cpufreq/scaling_governor=powersave",
+                                "cpu4/cpufreq/scaling_governor=powersave",
+                                "cpu6/cpufreq/scaling_governor=powersave"]);
+
+        for id in 0..7 {
+            assert_eq!(Cpu::with_backend(backend.clone(), id).get_policy().unwrap().governor, "powersave");
+        }
+    }
+
+    #[test]
+    fn subsets_use_their_lowest_cpu() {
+        let backend = machine();
+        let report = Bulk::with_backend(backend.clone(), "2-5".parse().unwrap()).get_freq();
+
+        assert_eq!(report.groups().iter().map(|group| group.0.to_string()).collect::<Vec<String>>(),
+                   vec!["2-3", "4-5"]);
+        assert!(report.get(3).unwrap().is_ok());
+        assert!(report.get(0).is_none());
+    }
+
+    #[test]
+    fn limits_are_written_in_a_valid_order() {
+        let backend = machine();
+        let (min, max) = Cpu::with_backend(backend.clone(), 0).get_hardware_limits().unwrap();
+        let bulk = Bulk::all_with(backend.clone());
+
+        assert!(bulk.set_limits(min, min).is_ok());
+        assert!(bulk.set_limits(max, max).is_ok());
+        assert_eq!(Cpu::with_backend(backend, 5).get_policy().unwrap(), Policy::new(max, max, "ondemand"));
+
+        match *bulk.set_limits(max, min).get(0).unwrap().as_ref().unwrap_err().kind() {
+            CpuPowerError::InvalidValue { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn failures_are_reported_per_cpu() {
+        let sysfs = Machine::new().with_cpus(&[0, 2]).with_offline(1).build();
+        let report = Bulk::new_in(&sysfs.root(), "0-2".parse().unwrap())
+            .set_policy(&Policy::new(800000, 1600000, "powersave"));
+
+        assert_eq!(report.succeeded(), CpuSet::from(&[0, 2][..]));
+        assert_eq!(report.failed(), CpuSet::from(&[1][..]));
+        assert!(!report.is_ok());
+        assert!(report.describe_errors().starts_with("cpus 1: "), "{}", report.describe_errors());
+
+        match *report.get(1).unwrap().as_ref().unwrap_err().kind() {
+            CpuPowerError::CpuOffline { id: 1, .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(sysfs.get(2, "scaling_max_freq"), "1600000");
+    }
+}
+
+
+mod hotplug {
+    extern crate errno;
+    extern crate libc;
+    use super::Machine;
+    use ::backend::Backend;
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::error::CpuPowerError;
+    use ::memory::InMemory;
+    use ::result::Result;
+    use std::sync::Arc;
+
+    /// The kernel refusing to take the cpu offline
+    #[derive(Debug)]
+    struct Busy(InMemory);
+
+    impl Backend for Busy {
+        fn read_attribute(&self, path: &str) -> Result<String> {
+            self.0.read_attribute(path)
+        }
+
+        fn write_attribute(&self, _path: &str, _value: &str) -> Result<()> {
+            Err(CpuPowerError::from(errno::Errno(libc::EBUSY)))
+        }
+
+        fn list_directory(&self, path: &str) -> Result<Vec<String>> {
+            self.0.list_directory(path)
+        }
+    }
+
+    #[test]
+    fn set_online_writes_the_switch() {
+        let sysfs = Machine::new().build();
+        let cpu = Cpu::new_in(&sysfs.root(), 2);
+
+        cpu.set_online(false).unwrap();
+        assert_eq!(sysfs.root().read_attribute("cpu2/online").unwrap(), "0");
+        assert!(!cpu.is_online().unwrap());
+
+        cpu.set_online(true).unwrap();
+        assert!(cpu.is_online().unwrap());
+    }
+
+    #[test]
+    fn cpus_without_switch_stay_online() {
+        let sysfs = Machine::new().build();
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
+
+        assert!(!cpu.is_hotpluggable().unwrap());
+        assert!(Cpu::new_in(&sysfs.root(), 1).is_hotpluggable().unwrap());
+        assert_eq!(Cpu::get_not_hotpluggable_in(&sysfs.root()).unwrap(), CpuSet::from(&[0][..]));
+
+        cpu.set_online(true).unwrap();
+        match *cpu.set_online(false).unwrap_err().kind() {
+            CpuPowerError::NotHotpluggable { id: 0 } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn refusals_are_reported() {
+        let backend = Arc::new(Busy(InMemory::new().with_attribute("cpu3/online", "1")));
+        let error = Cpu::with_backend(backend, 3).set_online(false).unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::HotplugRefused { id: 3, online: false, errno: errno::Errno(libc::EBUSY) } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(error.to_string().starts_with("Can't write \"0\" to online of cpu 3: The kernel refused"), "{}", error);
+    }
+
+    #[test]
+    fn missing_cpus_are_reported() {
+        let sysfs = Machine::new().build();
+
+        match *Cpu::new_in(&sysfs.root(), 7).set_online(true).unwrap_err().kind() {
+            CpuPowerError::CpuNotFound { id: 7 } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+}
+
+
+mod topology {
+    use super::{FakeSysfs, Machine};
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::error::CpuPowerError;
+    use ::fixture::Fixture;
+    use ::topology::Topology;
+    use std::sync::Arc;
+
+    fn set(list: &str) -> CpuSet {
+        list.parse().unwrap()
+    }
+
+    #[test]
+    fn cpus_are_grouped_by_package_and_core() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2, 3, 4, 5, 6]).with_offline(7).with_smt().build();
+        let topology = Topology::read_in(&sysfs.root()).unwrap();
+
+        assert_eq!(topology.get_cpus(), set("0-6"));
+        assert_eq!(topology.packages(), vec![0, 1]);
+        assert_eq!(topology.package(1), set("2-3,6"));
+        assert_eq!(topology.die(0, 0), set("0-1,4-5"));
+        assert_eq!(topology.core(0, 1), set("1,5"));
+        assert_eq!(topology.siblings(2), set("2,6"));
+        assert_eq!(topology.group_by_core(), vec![set("0,4"), set("1,5"), set("2,6"), set("3,7")]);
+        assert_eq!(topology.primary_threads(), set("0-3"));
+        assert!(topology.group_by_cluster().is_empty());
+    }
+
+    #[test]
+    fn cpu_reads_its_own_topology() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2, 3, 4, 5, 6]).with_offline(7).with_smt().build();
+        let topology = Cpu::new_in(&sysfs.root(), 6).get_topology().unwrap();
+
+        assert_eq!((topology.package, topology.die, topology.core, topology.cluster), (Some(1), Some(0), Some(0), None));
+        assert_eq!(topology.thread_siblings, set("2,6"));
+        assert!(topology.package_cpus.is_empty());
+
+        match *Cpu::new_in(&sysfs.root(), 7).get_topology().unwrap_err().kind() {
+            CpuPowerError::CpuOffline { id: 7, .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn missing_topology_is_not_blamed_on_cpufreq() {
+        let sysfs = FakeSysfs::new().with_file("cpu0/online", "1\n");
+        let error = Cpu::new_in(&sysfs.root(), 0).get_topology().unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::NotSupported{..} => (),
+            ref error => panic!("Wrong error appeared: {}", error)
+        };
+        assert!(error.to_string().starts_with("Can't read topology of cpu 0: "), "{}", error);
+    }
+
+    #[test]
+    fn topology_is_captured_in_fixtures() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2, 3, 4, 5, 6]).with_offline(7).with_smt().build();
+        let fixture = Arc::new(Fixture::capture(&sysfs.root()).unwrap());
+        let topology = Topology::read_with(fixture).unwrap();
+
+        assert_eq!(topology.package(1), set("2-3,6"));
+        assert_eq!(topology.siblings(5), set("1,5"));
+    }
+
+    #[test]
+    fn groups_convert_to_cpus() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2, 3, 4, 5, 6]).with_offline(7).with_smt().build();
+        let topology = Topology::read_in(&sysfs.root()).unwrap();
+        let cpus = topology.cpus(&topology.package(0));
+
+        assert_eq!(cpus.len(), 4);
+        assert_eq!(cpus[0].get_driver().unwrap(), "acpi-cpufreq");
+    }
+}
+
+mod hybrid {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::error::CpuPowerError;
+    use ::fixture::Fixture;
+    use ::hybrid::CoreClasses;
+    use std::sync::Arc;
+
+    fn set(list: &str) -> CpuSet {
+        list.parse().unwrap()
+    }
+
+    #[test]
+    fn cpus_are_classified_by_capacity() {
+        let sysfs = Machine::new().with_big_little().build();
+        let classes = CoreClasses::detect_in(&sysfs.root()).unwrap();
+
+        assert!(classes.is_hybrid());
+        assert_eq!(classes.efficiency_cores(), set("0-3"));
+        assert_eq!(classes.performance_cores(), set("4-5"));
+        assert_eq!(classes.classes()[0].capacity, Some(446));
+        assert_eq!(classes.classes()[0].hardware_limits, Some((500000, 1800000)));
+        assert_eq!(classes.classes()[1].hardware_limits, Some((800000, 2400000)));
+        assert_eq!(classes.class_of(5).unwrap().policies, vec![4]);
+        assert_eq!(Cpu::new_in(&sysfs.root(), 2).get_capacity().unwrap(), 446);
+    }
+
+    #[test]
+    fn cpus_are_classified_by_frequency_without_capacity() {
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2])
+            .set(2, "cpuinfo_max_freq", "3600000\n")
+            .with_file("cpu3/online", "1\n")
+            .build();
+        let classes = CoreClasses::detect_in(&sysfs.root()).unwrap();
+
+        assert_eq!(classes.classes().len(), 2);
+        assert_eq!(classes.classes()[0].capacity, None);
+        assert_eq!(classes.efficiency_cores(), set("0-1"));
+        assert_eq!(classes.performance_cores(), set("2"));
+        assert_eq!(classes.unclassified(), &set("3"));
+    }
+
+    #[test]
+    fn favored_cores_are_not_another_class() {
+        // Turbo Boost Max 3.0: cpu1 boosts a little higher than the others
+        let sysfs = Machine::new().with_cpus(&[0, 1, 2, 3])
+            .set_all("cpuinfo_max_freq", "4600000\n")
+            .set(1, "cpuinfo_max_freq", "4800000\n")
+            .build();
+        let classes = CoreClasses::detect_in(&sysfs.root()).unwrap();
+
+        assert!(!classes.is_hybrid());
+        assert_eq!(classes.performance_cores(), set("0-3"));
+        assert_eq!(classes.classes()[0].hardware_limits, Some((800000, 4800000)));
+        assert_eq!(classes.classes()[0].policies, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn homogeneous_cpus_are_all_performance_cores() {
+        let sysfs = Machine::new().with_cpus(&[0, 1]).build();
+        let classes = CoreClasses::detect_in(&sysfs.root()).unwrap();
+
+        assert!(!classes.is_hybrid());
+        assert!(classes.efficiency_cores().is_empty());
+        assert_eq!(classes.performance_cores(), set("0-1"));
+    }
+
+    #[test]
+    fn policies_are_found_by_their_cpus() {
+        // cpu5 came online first and named the policy of the big cpus
+        let sysfs = Machine::new().with_big_little().with_policies(&[(0, &[0, 1, 2, 3]), (5, &[4, 5])]).build();
+        let classes = CoreClasses::detect_in(&sysfs.root()).unwrap();
+
+        assert_eq!(classes.class_of(4).unwrap().policies, vec![5]);
+        assert_eq!(classes.policies(classes.class_of(4).unwrap())[0].get_related_cpus().unwrap(), set("4-5"));
+    }
+
+    #[test]
+    fn missing_capacity_is_not_blamed_on_cpufreq() {
+        let sysfs = Machine::new().build();
+        let error = Cpu::new_in(&sysfs.root(), 0).get_capacity().unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::NotSupported{..} => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(error.to_string().starts_with("Can't read cpu_capacity of cpu 0: "));
+    }
+
+    #[test]
+    fn classes_give_their_policies() {
+        let sysfs = Machine::new().with_big_little().build();
+        let fixture = Arc::new(Fixture::capture(&sysfs.root()).unwrap());
+        let classes = CoreClasses::detect_with(fixture).unwrap();
+        let policies = classes.policies(&classes.classes()[0]);
+
+        assert_eq!(policies.len(), 1);
+        assert_eq!(policies[0].get_related_cpus().unwrap(), set("0-3"));
+    }
+}
+
+mod intel_pstate {
+    use super::Machine;
+    use ::error::CpuPowerError;
+    use ::intel_pstate::{IntelPstate, PstateStatus};
+
+    #[test]
+    fn attributes_are_read() {
+        let sysfs = Machine::new().with_intel_pstate().build();
+        let pstate = IntelPstate::new_in(&sysfs.root());
+
+        assert!(pstate.is_available());
+        assert_eq!(pstate.get_status().unwrap(), PstateStatus::Active);
+        assert_eq!(pstate.get_min_perf_pct().unwrap(), 22);
+        assert_eq!(pstate.get_max_perf_pct().unwrap(), 100);
+        assert!(!pstate.get_no_turbo().unwrap());
+        assert!(!pstate.get_hwp_dynamic_boost().unwrap());
+        assert_eq!(pstate.get_num_pstates().unwrap(), 39);
+        assert_eq!(pstate.get_turbo_pct().unwrap(), 33);
+    }
+
+    #[test]
+    fn attributes_are_written() {
+        let sysfs = Machine::new().with_intel_pstate().build();
+        let pstate = IntelPstate::new_in(&sysfs.root());
+
+        pstate.set_status(PstateStatus::Passive).unwrap();
+        pstate.set_max_perf_pct(80).unwrap();
+        pstate.set_no_turbo(true).unwrap();
+
+        assert_eq!(pstate.get_status().unwrap(), PstateStatus::Passive);
+        assert_eq!(pstate.get_max_perf_pct().unwrap(), 80);
+        assert!(pstate.get_no_turbo().unwrap());
+    }
+
+    #[test]
+    fn percentages_above_100_are_rejected() {
+        let sysfs = Machine::new().with_intel_pstate().build();
+        let pstate = IntelPstate::new_in(&sysfs.root());
+
+        let error = pstate.set_min_perf_pct(120).unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::InvalidValue { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(error.context().unwrap().value, Some(String::from("120")));
+        assert!(error.to_string().starts_with("Can't write \"120\" to intel_pstate/min_perf_pct: "), "{}", error);
+        assert_eq!(pstate.get_min_perf_pct().unwrap(), 22);
+    }
+
+    #[test]
+    fn status_names_round_trip() {
+        for status in &[PstateStatus::Active, PstateStatus::Passive, PstateStatus::Off] {
+            assert_eq!(status.to_string().parse::<PstateStatus>().unwrap(), *status);
+        }
+        assert!("turbo".parse::<PstateStatus>().is_err());
+    }
+
+    #[test]
+    fn missing_driver_is_not_supported() {
+        let sysfs = Machine::new().build();
+        let pstate = IntelPstate::new_in(&sysfs.root());
+
+        assert!(!pstate.is_available());
+
+        let error = pstate.get_status().unwrap_err();
+        match *error.kind() {
+            CpuPowerError::NotSupported { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(error.context().unwrap().cpu, None);
+        assert!(error.to_string().starts_with("Can't read intel_pstate/status: "), "{}", error);
+    }
+}
+
+mod amd_pstate {
+    use super::Machine;
+    use ::amd_pstate::{AmdPstate, AmdPstateStatus};
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+
+    #[test]
+    fn status_is_read_and_written() {
+        let sysfs = Machine::new().with_amd_pstate().build();
+        let pstate = AmdPstate::new_in(&sysfs.root());
+
+        assert!(pstate.is_available());
+        assert_eq!(pstate.get_status().unwrap(), AmdPstateStatus::Active);
+        assert!(pstate.get_prefcore().unwrap());
+
+        pstate.set_status(AmdPstateStatus::Guided).unwrap();
+        assert_eq!(pstate.get_status().unwrap(), AmdPstateStatus::Guided);
+        assert_eq!("disable".parse::<AmdPstateStatus>().unwrap(), AmdPstateStatus::Disabled);
+    }
+
+    #[test]
+    fn performance_levels_are_read_per_cpu_and_policy() {
+        let sysfs = Machine::new().with_amd_pstate().build();
+        let perf = Cpu::new_in(&sysfs.root(), 1).get_amd_perf().unwrap();
+
+        assert_eq!(perf.highest_perf, 166);
+        assert_eq!(perf.max_freq, 4350000);
+        assert_eq!(perf.lowest_nonlinear_freq, 1400000);
+        assert_eq!(perf.prefcore_ranking, Some(236));
+        assert_eq!(perf.hw_prefcore, Some(true));
+        assert_eq!(FreqPolicy::new_in(&sysfs.root(), 0).get_amd_perf().unwrap(), perf);
+    }
+
+    #[test]
+    fn other_drivers_have_no_amd_perf() {
+        let sysfs = Machine::new().build();
+
+        match *Cpu::new_in(&sysfs.root(), 0).get_amd_perf().unwrap_err().kind() {
+            CpuPowerError::NotSupported { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(!AmdPstate::new_in(&sysfs.root()).is_available());
+    }
+
+    #[test]
+    fn status_errors_tell_the_knob() {
+        let sysfs = Machine::new().build();
+        let error = AmdPstate::new_in(&sysfs.root()).set_status(AmdPstateStatus::Passive).unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::NotSupported { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(error.to_string().starts_with("Can't write \"passive\" to amd_pstate/status: "), "{}", error);
+    }
+}
+
+mod epp {
+    use super::Machine;
+    use ::bulk::Bulk;
+    use ::cpu::Cpu;
+    use ::cpu_set::CpuSet;
+    use ::epp::Epp;
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+
+    #[test]
+    fn named_preferences_are_read_and_written() {
+        let sysfs = Machine::new().with_hwp().build();
+        let cpu = Cpu::new_in(&sysfs.root(), 1);
+
+        assert_eq!(cpu.get_epp().unwrap(), Epp::Named(String::from("balance_performance")));
+        assert_eq!(cpu.get_available_epps().unwrap().len(), 5);
+
+        cpu.set_epp("power").unwrap();
+        assert_eq!(sysfs.get(0, "energy_performance_preference"), "power");
+        assert_eq!(FreqPolicy::new_in(&sysfs.root(), 0).get_epp().unwrap(), Epp::from("power"));
+    }
+
+    #[test]
+    fn raw_values_are_written_as_numbers() {
+        let sysfs = Machine::new().with_hwp().build();
+        let cpu = Cpu::new_in(&sysfs.root(), 2);
+
+        cpu.set_epp(128).unwrap();
+        assert_eq!(sysfs.get(2, "energy_performance_preference"), "128");
+        assert_eq!(cpu.get_epp().unwrap(), Epp::Value(128));
+        assert_eq!("64".parse::<Epp>().unwrap(), Epp::Value(64));
+        assert!("256".parse::<Epp>().is_err());
+    }
+
+    #[test]
+    fn unavailable_preferences_are_rejected() {
+        let sysfs = Machine::new().with_hwp().build();
+        let error = Cpu::new_in(&sysfs.root(), 0).set_epp("balanced").unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::PreferenceUnavailable { ref preference, .. } if preference == "balanced" => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(error.context().unwrap().value, Some(String::from("balanced")));
+        assert_eq!(sysfs.get(0, "energy_performance_preference"), "balance_performance\n");
+    }
+
+    #[test]
+    fn preference_is_set_once_per_policy() {
+        let sysfs = Machine::new().with_hwp().build();
+        let report = Bulk::new_in(&sysfs.root(), "0-3".parse().unwrap()).set_epp("balance_power");
+
+        assert!(report.is_ok());
+        assert_eq!(report.groups().len(), 2);
+        assert_eq!(sysfs.get(2, "energy_performance_preference"), "balance_power");
+    }
+
+    #[test]
+    fn missing_epp_is_not_supported() {
+        let sysfs = Machine::new().build();
+        let report = Bulk::new_in(&sysfs.root(), CpuSet::from(&[0][..])).set_epp(Epp::Value(0));
+
+        assert_eq!(report.succeeded(), CpuSet::new());
+        match *Cpu::new_in(&sysfs.root(), 0).get_epp().unwrap_err().kind() {
+            CpuPowerError::NotSupported { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+}
+
+mod energy_perf_bias {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::energy_perf_bias::EnergyPerfBias;
+    use ::error::CpuPowerError;
+
+    #[test]
+    fn bias_is_read_and_written() {
+        let sysfs = Machine::new().with_energy_perf_bias(0, 6).build();
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
+
+        assert_eq!(cpu.get_energy_perf_bias().unwrap(), EnergyPerfBias::NORMAL);
+
+        cpu.set_energy_perf_bias(EnergyPerfBias::new(7).unwrap()).unwrap();
+        assert_eq!(cpu.get_energy_perf_bias().unwrap().value(), 7);
+        assert_eq!(cpu.get_energy_perf_bias().unwrap().name(), None);
+    }
+
+    #[test]
+    fn named_levels_round_trip() {
+        assert_eq!("balance-power".parse::<EnergyPerfBias>().unwrap(), EnergyPerfBias::BALANCE_POWER);
+        assert_eq!("15".parse::<EnergyPerfBias>().unwrap(), EnergyPerfBias::POWER);
+        assert_eq!(EnergyPerfBias::BALANCE_PERFORMANCE.to_string(), "balance-performance");
+        assert_eq!(EnergyPerfBias::new(3).unwrap().to_string(), "3");
+        assert!(EnergyPerfBias::new(16).is_err());
+        assert!("turbo".parse::<EnergyPerfBias>().is_err());
+    }
+
+    #[test]
+    fn missing_bias_is_reported() {
+        let sysfs = Machine::new().with_energy_perf_bias(0, 6).build();
+        let cpu = Cpu::new_in(&sysfs.root(), 1);
+
+        match *cpu.get_energy_perf_bias().unwrap_err().kind() {
+            CpuPowerError::FeatureMissing { id: 1, feature: "energy_perf_bias", .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        match *cpu.set_energy_perf_bias(EnergyPerfBias::POWER).unwrap_err().kind() {
+            CpuPowerError::FeatureMissing { id: 1, .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        match *Cpu::new_in(&sysfs.root(), 4).get_energy_perf_bias().unwrap_err().kind() {
+            CpuPowerError::CpuNotFound { id: 4 } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+}
+
+mod boost {
+    use super::{FakeSysfs, Machine};
+    use ::backend::Backend;
+    use ::boost::{Boost, BoostMechanism};
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+
+    fn read(sysfs: &FakeSysfs, path: &str) -> String {
+        sysfs.root().read_attribute(path).unwrap()
+    }
+
+    #[test]
+    fn global_boost_is_preferred() {
+        let sysfs = Machine::new().set_all("boost", "1\n").with_file("cpufreq/boost", "1\n").build();
+        let boost = Boost::detect_in(&sysfs.root()).unwrap();
+
+        assert_eq!(boost.get_mechanism(), BoostMechanism::Global);
+        assert!(boost.is_enabled().unwrap());
+
+        boost.set_enabled(false).unwrap();
+        assert!(!boost.is_enabled().unwrap());
+        assert_eq!(read(&sysfs, "cpufreq/boost"), "0");
+        assert!(boost.is_enabled_for(&FreqPolicy::new_in(&sysfs.root(), 0)).unwrap());
+    }
+
+    #[test]
+    fn no_turbo_is_inverted() {
+        let sysfs = Machine::new().with_intel_pstate().build();
+        let boost = Boost::detect_in(&sysfs.root()).unwrap();
+
+        assert_eq!(boost.get_mechanism(), BoostMechanism::IntelNoTurbo);
+        assert!(boost.is_enabled().unwrap());
+
+        boost.set_enabled(false).unwrap();
+        assert_eq!(read(&sysfs, "intel_pstate/no_turbo"), "1");
+    }
+
+    #[test]
+    fn per_policy_switches_are_all_written() {
+        let sysfs = Machine::new().set(0, "cpb", "1\n").set(2, "cpb", "0\n").build();
+        let boost = Boost::detect_in(&sysfs.root()).unwrap();
+
+        assert_eq!(boost.get_mechanism(), BoostMechanism::AmdCpb);
+        assert!(boost.get_mechanism().is_per_policy());
+        assert!(boost.is_enabled().unwrap());
+
+        boost.set_enabled(false).unwrap();
+        assert!(!boost.is_enabled().unwrap());
+
+        boost.set_enabled_for(&FreqPolicy::new_in(&sysfs.root(), 2), true).unwrap();
+        assert_eq!(sysfs.get(2, "cpb"), "1");
+        assert_eq!(sysfs.get(0, "cpb"), "0");
+    }
+
+    #[test]
+    fn errors_tell_the_switch() {
+        let sysfs = Machine::new().set(0, "boost", "1\n").build();
+        let boost = Boost::detect_in(&sysfs.root()).unwrap();
+        let error = boost.set_enabled_for(&FreqPolicy::new_in(&sysfs.root(), 2), true).unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::NotSupported { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(error.to_string().starts_with("Can't write \"1\" to boost of policy 2: "), "{}", error);
+
+        let sysfs = Machine::new().with_file("cpufreq/boost", "on\n").build();
+        let error = Boost::detect_in(&sysfs.root()).unwrap().is_enabled().unwrap_err();
+
+        match *error.kind() {
+            CpuPowerError::InvalidValue { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert!(error.to_string().starts_with("Can't read cpufreq/boost: "), "{}", error);
+    }
+
+    #[test]
+    fn missing_boost_is_not_supported() {
+        let sysfs = Machine::new().build();
+
+        match Boost::detect_in(&sysfs.root()).unwrap_err() {
+            CpuPowerError::NotSupported { .. } => {},
+            error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+}
+
+mod setspeed {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+    use ::simulator::{SimulatedPolicy, Simulator};
+    use std::fs;
+    use std::sync::Arc;
+
+    fn machine() -> Arc<Simulator> {
+        Arc::new(Simulator::new()
+            .with_policy(SimulatedPolicy::new(&[0, 1]))
+            .with_policy(SimulatedPolicy::new(&[2, 3]).governor("userspace")))
+    }
+
+    #[test]
+    fn other_governors_are_kept_unless_asked() {
+        let simulator = machine();
+        let cpu = Cpu::with_backend(simulator.clone(), 1);
+
+        match *cpu.set_setspeed(1600000, false).unwrap_err().kind() {
+            CpuPowerError::GovernorMismatch { ref governor, required: "userspace" } if governor == "ondemand" => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        match *cpu.get_setspeed().unwrap_err().kind() {
+            CpuPowerError::GovernorMismatch { .. } => {},
+            ref error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(cpu.get_policy().unwrap().governor, "ondemand");
+    }
+
+    #[test]
+    fn switching_reports_the_previous_governor() {
+        let simulator = machine();
+        let cpu = Cpu::with_backend(simulator.clone(), 0);
+        let result = cpu.set_setspeed(1600000, true).unwrap();
+
+        assert!(result.switched_governor());
+        assert_eq!(result.previous_governor, "ondemand");
+        assert_eq!(cpu.get_setspeed().unwrap(), 1600000);
+        assert_eq!(cpu.get_freq().unwrap(), 1600000);
+
+        cpu.modify_policy_governor(&result.previous_governor).unwrap();
+        assert_eq!(cpu.get_policy().unwrap().governor, "ondemand");
+    }
+
+    #[test]
+    fn userspace_policies_are_set_directly() {
+        let simulator = machine();
+        let result = FreqPolicy::with_backend(simulator.clone(), 2).set_setspeed(1600000, false).unwrap();
+
+        assert!(!result.switched_governor());
+        assert_eq!(Cpu::with_backend(simulator, 3).get_setspeed().unwrap(), 1600000);
+    }
+
+    #[test]
+    fn refused_frequency_restores_the_governor() {
+        let sysfs = Machine::new().build();
+        let setspeed = sysfs.root().path().join("cpu0/cpufreq/scaling_setspeed");
+        fs::remove_file(&setspeed).unwrap();
+        fs::create_dir(&setspeed).unwrap();
+        let cpu = Cpu::new_in(&sysfs.root(), 0);
+
+        assert!(cpu.set_setspeed(1600000, true).is_err());
+        assert_eq!(sysfs.get(0, "scaling_governor"), "ondemand");
+    }
+}
+
+mod tunables {
+    use super::Machine;
+    use ::cpu::Cpu;
+    use ::error::CpuPowerError;
+    use ::freq_policy::FreqPolicy;
+    use ::tunables::{GovernorTunables, Tunable, TunablesScope};
+
+    #[test]
+    fn global_tunables_are_found() {
+        let sysfs = Machine::new().with_tunables("ondemand", TunablesScope::Global).build();
+        let tunables = Cpu::new_in(&sysfs.root(), 1).get_governor_tunables().unwrap();
+
+        assert_eq!(tunables.get_governor(), "ondemand");
+        assert_eq!(tunables.get_scope(), TunablesScope::Global);
+        assert_eq!(tunables.get(Tunable::UpThreshold).unwrap(), 95);
+        assert_eq!(tunables.get_all().unwrap().len(), 5);
+
+        tunables.set(Tunable::SamplingRate, 20000).unwrap();
+        assert_eq!(tunables.get(Tunable::SamplingRate).unwrap(), 20000);
+    }
+
+    #[test]
+    fn per_policy_tunables_are_found() {
+        let sysfs = Machine::new().with_tunables("conservative", TunablesScope::PerPolicy(2)).build();
+        let tunables = GovernorTunables::of_policy(&FreqPolicy::new_in(&sysfs.root(), 2)).unwrap();
+
+        assert_eq!(tunables.get_scope(), TunablesScope::PerPolicy(2));
+        assert_eq!(tunables.get(Tunable::FreqStep).unwrap(), 5);
+
+        tunables.set(Tunable::FreqStep, 10).unwrap();
+        assert_eq!(Cpu::new_in(&sysfs.root(), 3).get_governor_tunables().unwrap().get(Tunable::FreqStep).unwrap(), 10);
+    }
+
+    #[test]
+    fn values_are_validated() {
+        let sysfs = Machine::new().with_tunables("conservative", TunablesScope::PerPolicy(2)).build();
+        let tunables = GovernorTunables::of_policy(&FreqPolicy::new_in(&sysfs.root(), 2)).unwrap();
+
+        for &(tunable, value) in &[(Tunable::UpThreshold, 101), (Tunable::UpThreshold, 20), (Tunable::DownThreshold, 80),
+                                   (Tunable::SamplingDownFactor, 11), (Tunable::SamplingRate, 0)] {
+            match tunables.set(tunable, value).unwrap_err() {
+                CpuPowerError::InvalidValue { .. } => {},
+                error => panic!("Wrong error appeared for {} = {}: {}", tunable, value, error)
+            }
+        }
+        match tunables.get(Tunable::PowersaveBias).unwrap_err() {
+            CpuPowerError::NotSupported { .. } => {},
+            error => panic!("Wrong error appeared: {}", error)
+        }
+        assert_eq!(tunables.get(Tunable::UpThreshold).unwrap(), 80);
+    }
+
+    #[test]
+    fn schedutil_rate_limit_is_read_and_written() {
+        let sysfs = Machine::new().with_tunables("schedutil", TunablesScope::PerPolicy(0))
+            .with_file("cpufreq/policy0/schedutil/future_knob", "7\n")
+            .build();
+        let tunables = GovernorTunables::of_cpu_for(&Cpu::new_in(&sysfs.root(), 1), "schedutil").unwrap();
+
+        assert_eq!(tunables.get(Tunable::RateLimitUs).unwrap(), 1000);
+        assert_eq!(tunables.get_all().unwrap(), vec![(Tunable::RateLimitUs, 1000)]);
+
+        tunables.set(Tunable::RateLimitUs, 500).unwrap();
+        assert_eq!(tunables.get_raw("rate_limit_us").unwrap(), "500");
+    }
+
+    #[test]
+    fn unknown_tunables_are_reached_by_name() {
+        let sysfs = Machine::new().with_tunables("schedutil", TunablesScope::PerPolicy(0))
+            .with_file("cpufreq/policy0/schedutil/future_knob", "7\n")
+            .build();
+        let tunables = GovernorTunables::of_policy_for(&FreqPolicy::new_in(&sysfs.root(), 0), "schedutil").unwrap();
+
+        assert_eq!(tunables.names().unwrap(), vec!["future_knob", "rate_limit_us"]);
+        tunables.set_raw("future_knob", "8").unwrap();
+        assert_eq!(tunables.get_raw("future_knob").unwrap(), "8");
+        assert!(tunables.set_raw("rate_limit_us", "-1").is_err());
+    }
+
+    #[test]
+    fn raw_names_stay_in_the_governor_directory() {
+        let sysfs = Machine::new().with_tunables("schedutil", TunablesScope::PerPolicy(0))
+            .with_file("cpufreq/policy0/schedutil/future_knob", "7\n")
+            .build();
+        let tunables = GovernorTunables::of_policy_for(&FreqPolicy::new_in(&sysfs.root(), 0), "schedutil").unwrap();
+
+        for name in &["../scaling_governor", "..", ".", "", "schedutil/rate_limit_us"] {
+            match tunables.get_raw(name).unwrap_err() {
+                CpuPowerError::InvalidValue{..} => {},
+                error => panic!("Wrong error appeared: {}", error)
+            }
+            match tunables.set_raw(name, "performance").unwrap_err() {
+                CpuPowerError::InvalidValue{..} => {},
+                error => panic!("Wrong error appeared: {}", error)
+            }
+        }
+        assert_eq!(FreqPolicy::new_in(&sysfs.root(), 0).get_policy().unwrap().governor, "schedutil");
+    }
+
+    #[test]
+    fn schedutil_tunables_need_schedutil() {
+        let sysfs = Machine::new().with_tunables("ondemand", TunablesScope::Global).build();
+
+        match GovernorTunables::of_cpu_for(&Cpu::new_in(&sysfs.root(), 0), "schedutil").unwrap_err() {
+            CpuPowerError::GovernorMismatch { ref governor, required: "schedutil" } if governor == "ondemand" => {},
+            error => panic!("Wrong error appeared: {}", error)
+        }
+    }
+
+    #[test]
+    fn other_governors_have_no_tunables() {
+        let sysfs = Machine::new().set(0, "scaling_governor", "performance\n").build();
+
+        match GovernorTunables::of_cpu(&Cpu::new_in(&sysfs.root(), 0)).unwrap_err() {
+            CpuPowerError::GovernorMismatch { ref governor, .. } if governor == "performance" => {},
+            error => panic!("Wrong error appeared: {}", error)
+        }
+    }
 }
@@ -0,0 +1,178 @@
+//! # Cpu topology
+//!
+//! Where each cpu sits in the machine, as described by
+//! `/sys/devices/system/cpu/cpuN/topology`: the package (socket), the die
+//! inside the package, the cluster and the core it belongs to, and the
+//! cpus sharing that core (hyper-threads). `Topology` keeps it for all the
+//! online cpus and groups them, e.g. to act on every cpu of a package.
+
+use ::backend::{Backend, default_backend};
+use ::cpu::Cpu;
+use ::cpu_set::CpuSet;
+use ::result::Result;
+use ::sysfs::SysfsRoot;
+use ::types::{CpuId, CpuMask};
+
+use std::collections::BTreeMap;
+use std::iter;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+/// Position of a single cpu. Ids are `None` when the kernel doesn't know
+/// them (missing attribute or `-1`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuTopology {
+    pub id: CpuId,
+    /// `physical_package_id`, the socket
+    pub package: Option<u32>,
+    /// `die_id`, unique within the package
+    pub die: Option<u32>,
+    /// `cluster_id`, cpus sharing e.g. an L2 cache
+    pub cluster: Option<u32>,
+    /// `core_id`, unique within the package
+    pub core: Option<u32>,
+    /// `thread_siblings_list`, the hardware threads of the core
+    pub thread_siblings: CpuSet,
+    /// `core_cpus_list`, same as the thread siblings on recent kernels
+    pub core_cpus: CpuSet,
+    /// `package_cpus_list` (`core_siblings_list` on older kernels)
+    pub package_cpus: CpuSet
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Topology {
+    cpus: BTreeMap<CpuId, CpuTopology>,
+    backend: Arc<dyn Backend>
+}
+
+
+impl Topology {
+    /// Topology of the online cpus of your system
+    pub fn read() -> Result<Topology> {
+        Topology::read_with(default_backend())
+    }
+
+    /// Topology of the online cpus of the given sysfs tree
+    pub fn read_in(root: &SysfsRoot) -> Result<Topology> {
+        Topology::read_with(Arc::new(root.clone()))
+    }
+
+    /// Topology of the online cpus according to the given backend, offline
+    /// ones having no topology
+    pub fn read_with(backend: Arc<dyn Backend>) -> Result<Topology> {
+        let mut cpus = BTreeMap::new();
+
+        for id in backend.get_cpus(CpuMask::Online)? {
+            cpus.insert(id, backend.get_topology(id)?);
+        }
+
+        Ok(Topology { cpus, backend })
+    }
+
+    /// Cpus the topology is known for
+    pub fn get_cpus(&self) -> CpuSet {
+        self.cpus.keys().cloned().collect()
+    }
+
+    pub fn get(&self, id: CpuId) -> Option<&CpuTopology> {
+        self.cpus.get(&id)
+    }
+
+    /// Handles for the cpus of the set, sharing the backend of the topology
+    pub fn cpus(&self, cpus: &CpuSet) -> Vec<Cpu> {
+        cpus.to_cpus_with(self.backend.clone())
+    }
+
+    /// Ids of the packages, sorted
+    pub fn packages(&self) -> Vec<u32> {
+        self.group_by_package().keys().cloned().collect()
+    }
+
+    /// Cpus of a package
+    pub fn package(&self, package: u32) -> CpuSet {
+        self.select(|cpu| cpu.package == Some(package))
+    }
+
+    /// Cpus of a die of a package
+    pub fn die(&self, package: u32, die: u32) -> CpuSet {
+        self.select(|cpu| cpu.package == Some(package) && cpu.die == Some(die))
+    }
+
+    /// Cpus of a cluster of a package
+    pub fn cluster(&self, package: u32, cluster: u32) -> CpuSet {
+        self.select(|cpu| cpu.package == Some(package) && cpu.cluster == Some(cluster))
+    }
+
+    /// Cpus of a core of a package
+    pub fn core(&self, package: u32, core: u32) -> CpuSet {
+        self.select(|cpu| cpu.package == Some(package) && cpu.core == Some(core))
+    }
+
+    /// Hardware threads sharing the core of the cpu, itself included
+    pub fn siblings(&self, id: CpuId) -> CpuSet {
+        match self.cpus.get(&id) {
+            Some(cpu) if !cpu.core_cpus.is_empty() => cpu.core_cpus.clone(),
+            Some(cpu) if !cpu.thread_siblings.is_empty() => cpu.thread_siblings.clone(),
+            Some(cpu) => iter::once(cpu.id).collect(),
+            None => CpuSet::new()
+        }
+    }
+
+    /// Cpus by package, those of unknown package are left out
+    pub fn group_by_package(&self) -> BTreeMap<u32, CpuSet> {
+        self.group_by(|cpu| cpu.package)
+    }
+
+    /// Cpus by package and die
+    pub fn group_by_die(&self) -> BTreeMap<(u32, u32), CpuSet> {
+        self.group_by(|cpu| match (cpu.package, cpu.die) {
+            (Some(package), Some(die)) => Some((package, die)),
+            _ => None
+        })
+    }
+
+    /// Cpus by package and cluster
+    pub fn group_by_cluster(&self) -> BTreeMap<(u32, u32), CpuSet> {
+        self.group_by(|cpu| match (cpu.package, cpu.cluster) {
+            (Some(package), Some(cluster)) => Some((package, cluster)),
+            _ => None
+        })
+    }
+
+    /// Cpus by physical core, ordered by their first cpu
+    pub fn group_by_core(&self) -> Vec<CpuSet> {
+        let mut cores: Vec<CpuSet> = Vec::new();
+
+        for id in self.cpus.keys() {
+            if !cores.iter().any(|core| core.contains(*id)) {
+                cores.push(self.siblings(*id));
+            }
+        }
+
+        cores
+    }
+
+    /// First hardware thread of every core
+    pub fn primary_threads(&self) -> CpuSet {
+        self.group_by_core().iter().filter_map(CpuSet::first).collect()
+    }
+
+    fn select<F: Fn(&CpuTopology) -> bool>(&self, filter: F) -> CpuSet {
+        self.cpus.values().filter(|cpu| filter(cpu)).map(|cpu| cpu.id).collect()
+    }
+
+    fn group_by<K: Ord, F: Fn(&CpuTopology) -> Option<K>>(&self, key: F) -> BTreeMap<K, CpuSet> {
+        let mut groups: BTreeMap<K, CpuSet> = BTreeMap::new();
+
+        for cpu in self.cpus.values() {
+            if let Some(key) = key(cpu) {
+                groups.entry(key).or_default().insert(cpu.id);
+            }
+        }
+
+        groups
+    }
+}
+
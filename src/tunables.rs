@@ -0,0 +1,292 @@
+//! # Tunables of the ondemand, conservative and schedutil governors
+//!
+//! While in use, these governors have a directory of tunables named after
+//! them: `cpufreq/policyN/<governor>` for drivers with per policy
+//! governors, `cpufreq/<governor>` shared by every policy otherwise.
+//! `GovernorTunables` finds the right one for a cpu or a policy and checks
+//! the values against the ranges the kernel accepts. Tunables unknown to
+//! the crate are reached by name.
+
+extern crate errno;
+extern crate libc;
+
+use ::backend::{self, Backend};
+use ::cpu::Cpu;
+use ::error::CpuPowerError;
+use ::freq_policy::FreqPolicy;
+use ::result::Result;
+
+use std::fmt;
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+
+const ONDEMAND: &str = "ondemand";
+const CONSERVATIVE: &str = "conservative";
+const SCHEDUTIL: &str = "schedutil";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tunable {
+    /// `sampling_rate`, how often the load is checked, in microseconds
+    SamplingRate,
+    /// `up_threshold`, load in percent above which the frequency goes up
+    UpThreshold,
+    /// `down_threshold`, load in percent below which the frequency goes
+    /// down (conservative)
+    DownThreshold,
+    /// `sampling_down_factor`, how many more samples to wait before going
+    /// down from the highest frequency
+    SamplingDownFactor,
+    /// `ignore_nice_load`, 1 to leave niced processes out of the load
+    IgnoreNiceLoad,
+    /// `powersave_bias`, in tenths of percent to lower the frequencies
+    /// picked (ondemand)
+    PowersaveBias,
+    /// `io_is_busy`, 1 to count waiting for I/O as load (ondemand)
+    IoIsBusy,
+    /// `freq_step`, frequency change of each step in percent of the
+    /// highest frequency (conservative)
+    FreqStep,
+    /// `rate_limit_us`, shortest time between two frequency changes, in
+    /// microseconds (schedutil)
+    RateLimitUs
+}
+
+
+/// Where the tunables are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TunablesScope {
+    /// `cpufreq/<governor>`, shared by every policy
+    Global,
+    /// `cpufreq/policyN/<governor>`
+    PerPolicy(u32)
+}
+
+
+#[derive(Debug, Clone)]
+pub struct GovernorTunables {
+    governor: String,
+    scope: TunablesScope,
+    backend: Arc<dyn Backend>
+}
+
+
+const TUNABLES: [Tunable; 9] = [
+    Tunable::SamplingRate,
+    Tunable::UpThreshold,
+    Tunable::DownThreshold,
+    Tunable::SamplingDownFactor,
+    Tunable::IgnoreNiceLoad,
+    Tunable::PowersaveBias,
+    Tunable::IoIsBusy,
+    Tunable::FreqStep,
+    Tunable::RateLimitUs
+];
+
+
+impl Tunable {
+    /// Every tunable, whatever the governor
+    pub fn all() -> &'static [Tunable] {
+        &TUNABLES
+    }
+
+    /// Name of the attribute
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tunable::SamplingRate => "sampling_rate",
+            Tunable::UpThreshold => "up_threshold",
+            Tunable::DownThreshold => "down_threshold",
+            Tunable::SamplingDownFactor => "sampling_down_factor",
+            Tunable::IgnoreNiceLoad => "ignore_nice_load",
+            Tunable::PowersaveBias => "powersave_bias",
+            Tunable::IoIsBusy => "io_is_busy",
+            Tunable::FreqStep => "freq_step",
+            Tunable::RateLimitUs => "rate_limit_us"
+        }
+    }
+
+    /// Tunable of the given attribute name
+    pub fn from_name(name: &str) -> Option<Tunable> {
+        Tunable::all().iter().find(|tunable| tunable.name() == name).cloned()
+    }
+
+    /// Values accepted by the kernel for the governor, `None` if the
+    /// governor doesn't have the tunable
+    pub fn range(&self, governor: &str) -> Option<(u64, u64)> {
+        match (*self, governor) {
+            (Tunable::SamplingRate, ONDEMAND) | (Tunable::SamplingRate, CONSERVATIVE) => Some((1, u64::from(u32::MAX))),
+            (Tunable::UpThreshold, ONDEMAND) | (Tunable::UpThreshold, CONSERVATIVE) => Some((1, 100)),
+            (Tunable::IgnoreNiceLoad, ONDEMAND) | (Tunable::IgnoreNiceLoad, CONSERVATIVE) => Some((0, 1)),
+            (Tunable::SamplingDownFactor, ONDEMAND) => Some((1, 100000)),
+            (Tunable::SamplingDownFactor, CONSERVATIVE) => Some((1, 10)),
+            (Tunable::PowersaveBias, ONDEMAND) => Some((0, 1000)),
+            (Tunable::IoIsBusy, ONDEMAND) => Some((0, 1)),
+            (Tunable::DownThreshold, CONSERVATIVE) => Some((1, 99)),
+            (Tunable::FreqStep, CONSERVATIVE) => Some((0, 100)),
+            (Tunable::RateLimitUs, SCHEDUTIL) => Some((0, u64::from(u32::MAX))),
+            _ => None
+        }
+    }
+}
+
+
+impl fmt::Display for Tunable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+impl GovernorTunables {
+    /// Tunables of the governor in charge of the cpu
+    pub fn of_cpu(cpu: &Cpu) -> Result<GovernorTunables> {
+        let governor = cpu.get_policy()?.governor;
+        GovernorTunables::find(cpu.get_backend().clone(), FreqPolicy::of_cpu(cpu)?.get_id(), governor)
+    }
+
+    /// Tunables of the cpu, `GovernorMismatch` unless `governor` is in
+    /// charge, e.g. `GovernorTunables::of_cpu_for(&cpu, "schedutil")`
+    pub fn of_cpu_for(cpu: &Cpu, governor: &'static str) -> Result<GovernorTunables> {
+        let current = require(cpu.get_policy()?.governor, governor)?;
+        GovernorTunables::find(cpu.get_backend().clone(), FreqPolicy::of_cpu(cpu)?.get_id(), current)
+    }
+
+    /// Tunables of the governor in charge of the policy
+    pub fn of_policy(policy: &FreqPolicy) -> Result<GovernorTunables> {
+        let governor = policy.get_policy()?.governor;
+        GovernorTunables::find(policy.get_backend().clone(), policy.get_id(), governor)
+    }
+
+    /// Tunables of the policy, `GovernorMismatch` unless `governor` is in
+    /// charge
+    pub fn of_policy_for(policy: &FreqPolicy, governor: &'static str) -> Result<GovernorTunables> {
+        let current = require(policy.get_policy()?.governor, governor)?;
+        GovernorTunables::find(policy.get_backend().clone(), policy.get_id(), current)
+    }
+
+    /// Governor the tunables belong to
+    pub fn get_governor(&self) -> &str {
+        &self.governor
+    }
+
+    /// Whether the tunables are shared by every policy
+    pub fn get_scope(&self) -> TunablesScope {
+        self.scope
+    }
+
+    pub fn get(&self, tunable: Tunable) -> Result<u64> {
+        self.range(tunable)?;
+        backend::read_value(&*self.backend, &self.attribute(tunable.name()))
+    }
+
+    /// Change a tunable, for every policy if they are global. Values out
+    /// of range fail with `InvalidValue` before anything is written.
+    pub fn set(&self, tunable: Tunable, value: u64) -> Result<()> {
+        let (mut min, mut max) = self.range(tunable)?;
+
+        // conservative needs down_threshold < up_threshold
+        if self.governor == CONSERVATIVE {
+            match tunable {
+                Tunable::UpThreshold => min = min.max(self.get(Tunable::DownThreshold)? + 1),
+                Tunable::DownThreshold => max = max.min(self.get(Tunable::UpThreshold)?.saturating_sub(1)),
+                _ => {}
+            }
+        }
+
+        if value < min || value > max {
+            return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+        }
+
+        self.backend.write_attribute(&self.attribute(tunable.name()), &value.to_string())
+    }
+
+    /// Tunables of the governor with their value
+    pub fn get_all(&self) -> Result<Vec<(Tunable, u64)>> {
+        let mut result = Vec::new();
+
+        for tunable in Tunable::all().iter().filter(|tunable| tunable.range(&self.governor).is_some()) {
+            match self.get(*tunable) {
+                Ok(value) => result.push((*tunable, value)),
+                // Older kernels lack a few of them
+                Err(CpuPowerError::NotSupported{..}) => {},
+                Err(error) => return Err(error)
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Names of every tunable found in the directory of the governor,
+    /// including the ones unknown to `Tunable`, sorted
+    pub fn names(&self) -> Result<Vec<String>> {
+        self.backend.list_directory(&self.directory())
+    }
+
+    /// Read a tunable by name
+    pub fn get_raw(&self, name: &str) -> Result<String> {
+        self.backend.read_attribute(&self.attribute(check_name(name)?))
+    }
+
+    /// Write a tunable by name, known ones being validated like with `set`
+    pub fn set_raw(&self, name: &str, value: &str) -> Result<()> {
+        match Tunable::from_name(check_name(name)?) {
+            Some(tunable) if tunable.range(&self.governor).is_some() => self.set(tunable, value.trim().parse()?),
+            _ => self.backend.write_attribute(&self.attribute(name), value)
+        }
+    }
+
+    fn find(backend: Arc<dyn Backend>, policy: u32, governor: String) -> Result<GovernorTunables> {
+        if governor != ONDEMAND && governor != CONSERVATIVE && governor != SCHEDUTIL {
+            return Err(CpuPowerError::GovernorMismatch { governor, required: "ondemand, conservative or schedutil" });
+        }
+
+        let scope = if backend.list_directory(&format!("{}/{}", backend::policy_directory(policy), governor)).is_ok() {
+            TunablesScope::PerPolicy(policy)
+        } else if backend.list_directory(&format!("cpufreq/{}", governor)).is_ok() {
+            TunablesScope::Global
+        } else {
+            return Err(CpuPowerError::from(errno::Errno(libc::ENOENT)));
+        };
+
+        Ok(GovernorTunables { governor, scope, backend })
+    }
+
+    /// `NotSupported` if the governor doesn't have the tunable
+    fn range(&self, tunable: Tunable) -> Result<(u64, u64)> {
+        tunable.range(&self.governor).ok_or(CpuPowerError::from(errno::Errno(libc::ENOENT)))
+    }
+
+    fn directory(&self) -> String {
+        match self.scope {
+            TunablesScope::Global => format!("cpufreq/{}", self.governor),
+            TunablesScope::PerPolicy(policy) => format!("{}/{}", backend::policy_directory(policy), self.governor)
+        }
+    }
+
+    fn attribute(&self, name: &str) -> String {
+        format!("{}/{}", self.directory(), name)
+    }
+}
+
+
+/// `InvalidValue` unless the name is a file of the directory of the
+/// governor, so that nothing outside of it can be reached
+fn check_name(name: &str) -> Result<&str> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(CpuPowerError::from(errno::Errno(libc::EINVAL)));
+    }
+
+    Ok(name)
+}
+
+
+/// The governor in charge if it is the required one
+fn require(governor: String, required: &'static str) -> Result<String> {
+    if governor == required {
+        Ok(governor)
+    } else {
+        Err(CpuPowerError::GovernorMismatch { governor, required })
+    }
+}
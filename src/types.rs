@@ -1,2 +1,29 @@
 pub type CpuId = u32;
 pub type Frequency = u64;
+
+
+/// Cpu masks found directly under `/sys/devices/system/cpu`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuMask {
+    /// Cpus which may ever be plugged in
+    Possible,
+    /// Cpus physically present
+    Present,
+    /// Cpus running
+    Online,
+    /// Possible cpus not running, either hot-unplugged or absent
+    Offline
+}
+
+
+impl CpuMask {
+    /// Name of the mask file
+    pub fn name(self) -> &'static str {
+        match self {
+            CpuMask::Possible => "possible",
+            CpuMask::Present => "present",
+            CpuMask::Online => "online",
+            CpuMask::Offline => "offline"
+        }
+    }
+}